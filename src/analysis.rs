@@ -0,0 +1,672 @@
+//! Turns classified frames into a list of games.
+//!
+//! Like [`crate::classifier`], this module does not touch any browser API.
+
+use crate::classifier::Image;
+use std::{ops::AddAssign, ops::Range};
+
+/// Bump this whenever a change can affect the detected games, so that cached analyses are recomputed.
+pub const ANALYSER_VERSION: u32 = 14;
+
+/// Maps storyboard frames to video time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Timing {
+    /// Seconds between two frames
+    pub interval: f64,
+    /// Length of the video in seconds
+    pub duration: f64,
+}
+
+impl Timing {
+    /// Time in seconds at which a frame was captured
+    pub fn frame_time(&self, idx: usize) -> f64 {
+        f64::min(idx as f64 * self.interval, self.duration)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Game {
+    pub frames: Range<usize>,
+    /// Start time in seconds
+    pub start: f64,
+    /// End time in seconds
+    pub end: f64,
+    pub impostor: bool,
+    pub outcome: Outcome,
+    pub win_reason: WinReason,
+    pub meetings: Vec<Meeting>,
+    pub sabotages: Vec<Sabotage>,
+    /// When the player started spectating as a ghost, if they died
+    pub death: Option<Death>,
+    /// Every change of the task bar, in order
+    pub task_progress: Vec<TaskProgress>,
+}
+
+/// A discussion and vote, from the first to the last voting screen frame
+#[derive(Debug, Clone, PartialEq)]
+pub struct Meeting {
+    pub frames: Range<usize>,
+    /// Start time in seconds
+    pub start: f64,
+    /// End time in seconds
+    pub end: f64,
+    pub trigger: MeetingTrigger,
+    pub ejection: Ejection,
+}
+
+impl Meeting {
+    fn new(frames: Range<usize>, timing: &Timing, trigger: MeetingTrigger, ejection: Ejection) -> Meeting {
+        Meeting {
+            start: timing.frame_time(frames.start),
+            end: timing.frame_time(frames.end),
+            frames,
+            trigger,
+            ejection,
+        }
+    }
+}
+
+/// What called a meeting
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MeetingTrigger {
+    EmergencyButton,
+    BodyReport,
+    /// No splash screen was captured
+    Unknown,
+}
+
+impl MeetingTrigger {
+    /// Lowercase name, as used in text formats
+    pub fn name(self) -> &'static str {
+        match self {
+            MeetingTrigger::EmergencyButton => "emergency",
+            MeetingTrigger::BodyReport => "report",
+            MeetingTrigger::Unknown => "unknown",
+        }
+    }
+
+    /// Parses the output of [`MeetingTrigger::name`].
+    pub fn from_name(name: &str) -> Option<MeetingTrigger> {
+        match name {
+            "emergency" => Some(MeetingTrigger::EmergencyButton),
+            "report" => Some(MeetingTrigger::BodyReport),
+            "unknown" => Some(MeetingTrigger::Unknown),
+            _ => None,
+        }
+    }
+}
+
+/// A reactor meltdown or an oxygen depletion, while the alarm was visible
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sabotage {
+    pub frames: Range<usize>,
+    /// Start time in seconds
+    pub start: f64,
+    /// End time in seconds
+    pub end: f64,
+}
+
+impl Sabotage {
+    fn new(frames: Range<usize>, timing: &Timing) -> Sabotage {
+        Sabotage {
+            start: timing.frame_time(frames.start),
+            end: timing.frame_time(frames.end),
+            frames,
+        }
+    }
+}
+
+/// The first frame seen as a ghost
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Death {
+    pub frame: usize,
+    /// Time in seconds
+    pub time: f64,
+}
+
+/// Result of the vote ending a meeting
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Ejection {
+    Player,
+    NoOne,
+    /// No ejection screen was captured, or it was not recognized, as on every map but The Skeld
+    Unknown,
+}
+
+impl Ejection {
+    /// Lowercase name, as used in text formats
+    pub fn name(self) -> &'static str {
+        match self {
+            Ejection::Player => "player",
+            Ejection::NoOne => "no_one",
+            Ejection::Unknown => "unknown",
+        }
+    }
+
+    /// Parses the output of [`Ejection::name`].
+    pub fn from_name(name: &str) -> Option<Ejection> {
+        match name {
+            "player" => Some(Ejection::Player),
+            "no_one" => Some(Ejection::NoOne),
+            "unknown" => Some(Ejection::Unknown),
+            _ => None,
+        }
+    }
+
+    /// Combines what several frames of the same ejection show.
+    /// The ejected player can float out of the frame, so one frame showing them is enough.
+    fn merge(self, other: Ejection) -> Ejection {
+        match (self, other) {
+            (Ejection::Player, _) | (_, Ejection::Player) => Ejection::Player,
+            (Ejection::NoOne, _) | (_, Ejection::NoOne) => Ejection::NoOne,
+            _ => Ejection::Unknown,
+        }
+    }
+}
+
+/// How a game ended for the streamer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    Win,
+    Loss,
+    /// No victory or defeat screen was captured
+    Unknown,
+}
+
+impl Outcome {
+    /// Lowercase name, as used in text formats
+    pub fn name(self) -> &'static str {
+        match self {
+            Outcome::Win => "win",
+            Outcome::Loss => "loss",
+            Outcome::Unknown => "unknown",
+        }
+    }
+
+    /// Parses the output of [`Outcome::name`].
+    pub fn from_name(name: &str) -> Option<Outcome> {
+        match name {
+            "win" => Some(Outcome::Win),
+            "loss" => Some(Outcome::Loss),
+            "unknown" => Some(Outcome::Unknown),
+            _ => None,
+        }
+    }
+}
+
+/// How the crewmates won a game
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WinReason {
+    /// The task bar was filled
+    Tasks,
+    /// The last impostor was ejected. Only detected on The Skeld, whose ejection screen is the only one recognized.
+    Votes,
+    /// The impostors won, or the end of the game was not captured
+    Unknown,
+}
+
+impl WinReason {
+    /// Lowercase name, as used in text formats
+    pub fn name(self) -> &'static str {
+        match self {
+            WinReason::Tasks => "tasks",
+            WinReason::Votes => "votes",
+            WinReason::Unknown => "unknown",
+        }
+    }
+
+    /// Parses the output of [`WinReason::name`].
+    pub fn from_name(name: &str) -> Option<WinReason> {
+        match name {
+            "tasks" => Some(WinReason::Tasks),
+            "votes" => Some(WinReason::Votes),
+            "unknown" => Some(WinReason::Unknown),
+            _ => None,
+        }
+    }
+}
+
+/// The task bar at some point of a game
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TaskProgress {
+    pub frame: usize,
+    /// Time in seconds
+    pub time: f64,
+    /// Share of the crew's tasks done, from 0 to 1
+    pub completion: f64,
+}
+
+/// Share of the crew's tasks done when the task bar was last seen in a game.
+pub fn task_completion(game: &Game) -> Option<f64> {
+    game.task_progress.last().map(|progress| progress.completion)
+}
+
+/// Formats a time in seconds as `H:MM:SS`.
+pub fn format_timestamp(seconds: f64) -> String {
+    let seconds = seconds as u64;
+    format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+/// Lists every game with its start timestamp, role and outcome, one per line, ready to be posted as a comment.
+pub fn timestamp_summary(games: &[Game]) -> String {
+    let mut text = String::new();
+    for (idx, game) in games.iter().enumerate() {
+        text.push_str(&format!(
+            "{} Game {} \u{2014} {}",
+            format_timestamp(game.start),
+            idx + 1,
+            if game.impostor { "Impostor" } else { "Crewmate" }
+        ));
+        match game.outcome {
+            Outcome::Win => text.push_str(" \u{2014} Win"),
+            Outcome::Loss => text.push_str(" \u{2014} Loss"),
+            Outcome::Unknown => (),
+        }
+        match game.win_reason {
+            WinReason::Tasks => text.push_str(" (tasks)\n"),
+            WinReason::Votes => text.push_str(" (votes)\n"),
+            WinReason::Unknown => text.push('\n'),
+        }
+    }
+    text
+}
+
+/// Whether someone who watched the video up to `time` has seen the role of a game.
+/// Games start after the role reveal splash, so their start is enough.
+pub fn role_revealed(game: &Game, time: f64) -> bool {
+    time >= game.start
+}
+
+/// Whether someone who watched the video up to `time` knows if the player died in a game.
+pub fn death_revealed(game: &Game, time: f64) -> bool {
+    match game.death {
+        Some(death) => time >= death.time,
+        None => outcome_revealed(game, time),
+    }
+}
+
+/// Whether someone who watched the video up to `time` has seen how a game ended.
+pub fn outcome_revealed(game: &Game, time: f64) -> bool {
+    time >= game.end
+}
+
+/// Seconds after the start of a game during which going back jumps to the game before it.
+const REWIND_TOLERANCE: f64 = 3.0;
+
+/// The first game starting after `time`, with its index.
+pub fn next_game(games: &[Game], time: f64, impostor_only: bool) -> Option<(usize, &Game)> {
+    games
+        .iter()
+        .enumerate()
+        .find(|(_, game)| game.start > time && (game.impostor || !impostor_only))
+}
+
+/// The last game starting before `time`, with its index.
+/// A game that started less than a few seconds ago is skipped, so that going back repeatedly walks through the games.
+pub fn previous_game(games: &[Game], time: f64, impostor_only: bool) -> Option<(usize, &Game)> {
+    games
+        .iter()
+        .enumerate()
+        .rev()
+        .find(|(_, game)| game.start < time - REWIND_TOLERANCE && (game.impostor || !impostor_only))
+}
+
+/// Seconds before the start of a game that count as part of it, as seeking can land slightly early.
+const SEEK_TOLERANCE: f64 = 1.0;
+
+/// Where to seek from `time` so that only impostor games are played, or `None` to keep playing.
+///
+/// Nothing after `analysed_until` is skipped, since the games there are not known yet.
+/// Once the whole video is analysed, the part after the last impostor game is skipped to the end.
+pub fn skip_to(games: &[Game], time: f64, analysed_until: f64, duration: f64) -> Option<f64> {
+    if time >= analysed_until {
+        return None;
+    }
+    let mut impostor_games = games.iter().filter(|game| game.impostor);
+    if impostor_games
+        .clone()
+        .any(|game| game.start - SEEK_TOLERANCE <= time && time < game.end)
+    {
+        return None;
+    }
+    match impostor_games.find(|game| game.start > time) {
+        Some(game) => Some(game.start),
+        None if analysed_until >= duration => Some(duration),
+        None => None,
+    }
+}
+
+/// Removes the black frames padding the end of the last sprite sheet.
+/// Returns the number of removed frames.
+pub fn trim_black_frames(images: &mut Vec<Image>) -> usize {
+    let mut removed = 0;
+    while images.last().map(|image| image.is_black()).unwrap_or(false) {
+        images.pop();
+        removed += 1;
+    }
+    removed
+}
+
+/// Finds the games in a sequence of frames.
+pub fn find_games(images: &[Image], timing: &Timing) -> Vec<Game> {
+    let mut segmenter = Segmenter::new(*timing);
+    for image in images {
+        segmenter.push(image);
+    }
+    segmenter.finish()
+}
+
+/// Frames without the voting screen that don't split a meeting, as a frame can be misclassified
+const MAX_MEETING_GAP: usize = 1;
+
+/// Frames without the alarm that don't split a sabotage, as the alarm flashes
+const MAX_SABOTAGE_GAP: usize = 2;
+
+/// Frames after the last voting screen during which the ejection screen is expected
+const MAX_EJECTION_DELAY: usize = 3;
+
+/// Frames between the meeting splash and the first voting screen
+const MAX_TRIGGER_DELAY: usize = 3;
+
+/// Consecutive ghost frames needed to consider the player dead, as a frame can be misclassified
+const MIN_GHOST_FRAMES: usize = 2;
+
+/// Frames between the end of the last meeting and the victory or defeat screen of a game won by ejecting the last impostor
+const MAX_VOTE_WIN_DELAY: usize = 5;
+
+/// Task completion from which a crewmate victory that did not follow an ejection is a task win.
+/// The bar is rarely captured full, as the game ends as soon as the last task is done.
+const MIN_TASK_WIN_COMPLETION: f64 = 0.8;
+
+/// Groups the frames where something is seen into runs, tolerating a few frames without it.
+struct Runs {
+    max_gap: usize,
+    /// Frames of the current run, up to the last frame where it was seen
+    current: Option<Range<usize>>,
+}
+
+impl Runs {
+    fn new(max_gap: usize) -> Runs {
+        Runs { max_gap, current: None }
+    }
+
+    /// Returns the run that is over at this frame, if any.
+    fn push(&mut self, idx: usize, seen: bool) -> Option<Range<usize>> {
+        if seen {
+            match &mut self.current {
+                Some(run) => run.end = idx + 1,
+                None => self.current = Some(idx..idx + 1),
+            }
+            None
+        } else if self.current.as_ref().map(|run| idx >= run.end + self.max_gap).unwrap_or(false) {
+            self.current.take()
+        } else {
+            None
+        }
+    }
+
+    fn is_running(&self) -> bool {
+        self.current.is_some()
+    }
+
+    fn finish(&mut self) -> Option<Range<usize>> {
+        self.current.take()
+    }
+}
+
+struct GameInProgress {
+    start: usize,
+    /// Role shown by the splash screen before the game, if it was captured
+    revealed_impostor: Option<bool>,
+    impostor_objectives_count: usize,
+    ingame_frames_count: usize,
+    meeting: Runs,
+    meeting_trigger: MeetingTrigger,
+    meeting_ejection: Ejection,
+    /// Last meeting splash, with its frame
+    last_splash: Option<(usize, MeetingTrigger)>,
+    meetings: Vec<Meeting>,
+    sabotage: Runs,
+    sabotages: Vec<Sabotage>,
+    /// First frame and length of the current run of ghost frames
+    ghost_run: Option<(usize, usize)>,
+    death: Option<usize>,
+    task_progress: Vec<TaskProgress>,
+}
+
+impl GameInProgress {
+    fn new(start: usize, revealed_impostor: Option<bool>) -> GameInProgress {
+        GameInProgress {
+            start,
+            revealed_impostor,
+            impostor_objectives_count: 0,
+            ingame_frames_count: 1,
+            meeting: Runs::new(MAX_MEETING_GAP),
+            meeting_trigger: MeetingTrigger::Unknown,
+            meeting_ejection: Ejection::Unknown,
+            last_splash: None,
+            meetings: Vec::new(),
+            sabotage: Runs::new(MAX_SABOTAGE_GAP),
+            sabotages: Vec::new(),
+            ghost_run: None,
+            death: None,
+            task_progress: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, idx: usize, image: &Image, timing: &Timing) {
+        if image.emergency_meeting || image.body_reported {
+            let trigger = if image.emergency_meeting {
+                MeetingTrigger::EmergencyButton
+            } else {
+                MeetingTrigger::BodyReport
+            };
+            self.last_splash = Some((idx, trigger));
+        }
+
+        if image.council && !self.meeting.is_running() {
+            self.meeting_trigger = match self.last_splash.take() {
+                Some((splash, trigger)) if idx <= splash + MAX_TRIGGER_DELAY => trigger,
+                _ => MeetingTrigger::Unknown,
+            };
+        }
+        if let Some(frames) = self.meeting.push(idx, image.council) {
+            self.end_meeting(frames, timing);
+        }
+        if image.ejection {
+            self.record_ejection(idx, if image.player_ejected { Ejection::Player } else { Ejection::NoOne });
+        }
+
+        if let Some(frames) = self.sabotage.push(idx, image.sabotage_alarm) {
+            self.sabotages.push(Sabotage::new(frames, timing));
+        }
+
+        if self.death.is_none() {
+            if image.ghost {
+                let (start, length) = self.ghost_run.get_or_insert((idx, 0));
+                *length += 1;
+                if *length >= MIN_GHOST_FRAMES {
+                    self.death = Some(*start);
+                }
+            } else if image.is_game() && !image.council && !image.alert && !image.open_map && !image.sabotage_alarm {
+                // Only plain gameplay shows the buttons of a living player, meetings and alarms say nothing
+                self.ghost_run = None;
+            }
+        }
+
+        if let Some(completion) = image.task_completion {
+            if self.task_progress.last().map(|progress| progress.completion) != Some(completion) {
+                self.task_progress.push(TaskProgress {
+                    frame: idx,
+                    time: timing.frame_time(idx),
+                    completion,
+                });
+            }
+        }
+
+        if image.impostor_objective && !image.alert {
+            self.impostor_objectives_count.add_assign(1);
+        }
+        if image.is_game() && !image.open_map && !image.council && !image.alert {
+            self.ingame_frames_count.add_assign(1);
+        }
+    }
+
+    fn end_meeting(&mut self, frames: Range<usize>, timing: &Timing) {
+        self.meetings.push(Meeting::new(frames, timing, self.meeting_trigger, self.meeting_ejection));
+        self.meeting_ejection = Ejection::Unknown;
+    }
+
+    /// Whether an ejection screen can be shown at this frame, during a meeting or right after it
+    fn expects_ejection(&self, idx: usize) -> bool {
+        self.meeting.is_running() || self.meetings.last().map(|meeting| idx < meeting.frames.end + MAX_EJECTION_DELAY).unwrap_or(false)
+    }
+
+    /// Attaches an ejection screen to the meeting it follows.
+    fn record_ejection(&mut self, idx: usize, ejection: Ejection) {
+        if self.meeting.is_running() {
+            self.meeting_ejection = self.meeting_ejection.merge(ejection);
+        } else if let Some(meeting) = self.meetings.last_mut() {
+            if idx < meeting.frames.end + MAX_EJECTION_DELAY {
+                meeting.ejection = meeting.ejection.merge(ejection);
+            }
+        }
+    }
+
+    /// Tells how the crewmates won, when the game ended at frame `end` with a victory for them.
+    fn win_reason(&self, end: usize) -> WinReason {
+        let voted_out = self
+            .meetings
+            .last()
+            .map(|meeting| meeting.ejection == Ejection::Player && end <= meeting.frames.end + MAX_VOTE_WIN_DELAY)
+            .unwrap_or(false);
+        let completion = self.task_progress.last().map(|progress| progress.completion);
+        if voted_out {
+            WinReason::Votes
+        } else if completion.map(|completion| completion >= MIN_TASK_WIN_COMPLETION).unwrap_or(false) {
+            WinReason::Tasks
+        } else {
+            WinReason::Unknown
+        }
+    }
+
+    /// Ends the running meeting and sabotage, if any.
+    fn finish(&mut self, timing: &Timing) {
+        if let Some(frames) = self.meeting.finish() {
+            self.end_meeting(frames, timing);
+        }
+        if let Some(frames) = self.sabotage.finish() {
+            self.sabotages.push(Sabotage::new(frames, timing));
+        }
+    }
+}
+
+/// Splits frames into games as they arrive.
+pub struct Segmenter {
+    timing: Timing,
+    analysed_frames: usize,
+    /// Frames after the last non-black frame don't extend the last game
+    last_content_frame: Option<usize>,
+    /// Role splash seen since the last game ended
+    revealed_impostor: Option<bool>,
+    current_game: Option<GameInProgress>,
+    games: Vec<Game>,
+}
+
+impl Segmenter {
+    pub fn new(timing: Timing) -> Segmenter {
+        Segmenter {
+            timing,
+            analysed_frames: 0,
+            last_content_frame: None,
+            revealed_impostor: None,
+            current_game: None,
+            games: Vec::new(),
+        }
+    }
+
+    /// Number of frames pushed so far
+    pub fn analysed_frames(&self) -> usize {
+        self.analysed_frames
+    }
+
+    /// The games that are already over
+    pub fn games(&self) -> &[Game] {
+        &self.games
+    }
+
+    pub fn push(&mut self, image: &Image) {
+        let idx = self.analysed_frames;
+        self.analysed_frames += 1;
+        if !image.is_black() {
+            self.last_content_frame = Some(idx);
+        }
+
+        // The ejection of a red or cyan player looks like a role splash, but it only follows a vote
+        let after_vote = self.current_game.as_ref().map(|game| game.expects_ejection(idx)).unwrap_or(false);
+        if (image.impostor_reveal || image.crewmate_reveal) && !after_vote {
+            // A new game is starting, even if the end of the previous one was missed
+            self.end_game(idx, Outcome::Unknown);
+            self.revealed_impostor = Some(image.impostor_reveal);
+        } else if image.is_game() && self.current_game.is_none() {
+            self.current_game = Some(GameInProgress::new(idx, self.revealed_impostor.take()));
+        } else if image.victory_screen || image.game_settings || image.defeat_screen {
+            let outcome = if image.victory_screen {
+                Outcome::Win
+            } else if image.defeat_screen {
+                Outcome::Loss
+            } else {
+                Outcome::Unknown
+            };
+            self.end_game(idx, outcome);
+            self.revealed_impostor = None;
+        }
+
+        if let Some(game) = &mut self.current_game {
+            game.push(idx, image, &self.timing);
+        }
+    }
+
+    /// Ends the running game, if any, and returns all the games.
+    pub fn finish(mut self) -> Vec<Game> {
+        let end = self.last_content_frame.map(|idx| idx + 1).unwrap_or(0);
+        self.end_game(end, Outcome::Unknown);
+        self.games
+    }
+
+    fn end_game(&mut self, end: usize, outcome: Outcome) {
+        let mut game = match self.current_game.take() {
+            Some(game) => game,
+            None => return,
+        };
+        if end <= game.start + 2 {
+            return;
+        }
+
+        game.finish(&self.timing);
+
+        // The splash is reliable, the objectives are only a fallback when it was not captured
+        let ratio = game.impostor_objectives_count as f64 / game.ingame_frames_count as f64;
+        let impostor = game.revealed_impostor.unwrap_or(ratio > 0.6);
+        let crewmates_won = match outcome {
+            Outcome::Win => !impostor,
+            Outcome::Loss => impostor,
+            Outcome::Unknown => false,
+        };
+        self.games.push(Game {
+            frames: game.start..end,
+            start: self.timing.frame_time(game.start),
+            end: self.timing.frame_time(end),
+            impostor,
+            outcome,
+            win_reason: if crewmates_won { game.win_reason(end) } else { WinReason::Unknown },
+            meetings: game.meetings,
+            sabotages: game.sabotages,
+            death: game.death.map(|frame| Death {
+                frame,
+                time: self.timing.frame_time(frame),
+            }),
+            task_progress: game.task_progress,
+        });
+    }
+}
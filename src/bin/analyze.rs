@@ -0,0 +1,143 @@
+//! Runs the analysis on storyboard sprite sheets stored on disk.
+//!
+//! Usage: `analyze <storyboard spec> <directory> [level]`
+//!
+//! The directory must contain the sheets of the selected level named `M0.jpg`, `M1.jpg`, ...
+
+use addon::{
+    analysis::{find_games, format_timestamp, trim_black_frames},
+    classifier::{split_sheet, Image, FRAME_HEIGHT, FRAME_WIDTH},
+    ytimg::{parse_value, Endpoint},
+};
+use std::path::Path;
+
+fn load_sheet(path: &Path, endpoint: &Endpoint) -> Result<Vec<Image>, String> {
+    let sheet = image::io::Reader::open(path)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(|e| format!("cannot read {}: {}", path.display(), e))?
+        .decode()
+        .map_err(|e| format!("cannot decode {}: {}", path.display(), e))?
+        .to_rgba8();
+    let width = endpoint.columns() as u32 * endpoint.image_width() as u32;
+    let height = endpoint.rows() as u32 * endpoint.image_height() as u32;
+    if sheet.width() > width || sheet.height() > height {
+        return Err(format!(
+            "{} is {}x{}, expected at most {}x{}",
+            path.display(),
+            sheet.width(),
+            sheet.height(),
+            width,
+            height
+        ));
+    }
+
+    // The last sheet can be smaller than the grid. Pad it with black like the browser canvas does.
+    let mut data = vec![0; width as usize * height as usize * 4];
+    for (y, row) in sheet.rows().enumerate() {
+        for (x, pixel) in row.enumerate() {
+            let offset = (y * width as usize + x) * 4;
+            data[offset..offset + 4].copy_from_slice(&pixel.0);
+        }
+    }
+
+    Ok(split_sheet(
+        &data,
+        endpoint.columns(),
+        endpoint.rows(),
+        endpoint.image_width(),
+        endpoint.image_height(),
+    ))
+}
+
+fn run(spec: String, directory: &Path, level: usize) -> Result<(), String> {
+    let endpoints = parse_value(spec).map_err(|e| e.to_string())?;
+    let endpoint = endpoints
+        .get(level)
+        .ok_or_else(|| format!("the storyboard has no level {}", level))?;
+    if endpoint.image_width() != FRAME_WIDTH || endpoint.image_height() != FRAME_HEIGHT {
+        return Err(format!(
+            "level {} has {}x{} frames, the classifier expects {}x{}",
+            level,
+            endpoint.image_width(),
+            endpoint.image_height(),
+            FRAME_WIDTH,
+            FRAME_HEIGHT
+        ));
+    }
+
+    let mut images = Vec::new();
+    for n in 0..endpoint.sheet_count() {
+        let path = directory.join(format!("M{}.jpg", n));
+        if !path.exists() {
+            return Err(format!("missing {} ({} sheets expected)", path.display(), endpoint.sheet_count()));
+        }
+        images.append(&mut load_sheet(&path, endpoint)?);
+    }
+    images.truncate(endpoint.frame_count());
+
+    let removed = trim_black_frames(&mut images);
+    println!("{} sheets, {} frames ({} black frames removed)", endpoint.sheet_count(), images.len(), removed);
+
+    let timing = endpoint.timing(None).map_err(|e| e.to_string())?;
+    let games = find_games(&images, &timing);
+    for (idx, game) in games.iter().enumerate() {
+        println!(
+            "Game {}: {} - {} (frames {}..{}) {}, {} ({})",
+            idx + 1,
+            format_timestamp(game.start),
+            format_timestamp(game.end),
+            game.frames.start,
+            game.frames.end,
+            if game.impostor { "Impostor" } else { "Crewmate" },
+            game.outcome.name(),
+            game.win_reason.name()
+        );
+        for meeting in &game.meetings {
+            println!(
+                "  Meeting: {} - {}, called by: {}, ejected: {}",
+                format_timestamp(meeting.start),
+                format_timestamp(meeting.end),
+                meeting.trigger.name(),
+                meeting.ejection.name()
+            );
+        }
+        for sabotage in &game.sabotages {
+            println!("  Sabotage: {} - {}", format_timestamp(sabotage.start), format_timestamp(sabotage.end));
+        }
+        if let Some(death) = game.death {
+            println!("  Died at {} (frame {})", format_timestamp(death.time), death.frame);
+        }
+        for progress in &game.task_progress {
+            println!("  Tasks: {:.0}% at {}", progress.completion * 100.0, format_timestamp(progress.time));
+        }
+    }
+    if games.is_empty() {
+        println!("No game detected");
+    }
+
+    Ok(())
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let (spec, directory) = match (args.next(), args.next()) {
+        (Some(spec), Some(directory)) => (spec, directory),
+        _ => {
+            eprintln!("Usage: analyze <storyboard spec> <directory> [level]");
+            std::process::exit(2);
+        }
+    };
+    let level = match args.next().map(|level| level.parse()) {
+        Some(Ok(level)) => level,
+        Some(Err(_)) => {
+            eprintln!("level must be a number");
+            std::process::exit(2);
+        }
+        None => 2,
+    };
+
+    if let Err(e) = run(spec, Path::new(&directory), level) {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
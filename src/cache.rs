@@ -0,0 +1,227 @@
+//! Stores finished analyses in IndexedDB so that reopening a video does not download it again.
+
+use crate::{
+    analysis::{
+        Death, Ejection, Game, Meeting, MeetingTrigger, Outcome, Sabotage, TaskProgress, Timing, WinReason,
+        ANALYSER_VERSION,
+    },
+    error::AnalysisError,
+};
+use js_sys::Promise;
+use wasm_bindgen::{prelude::*, JsCast};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::*;
+
+const DATABASE_NAME: &str = "among_us_addon";
+const STORE_NAME: &str = "analyses";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CachedAnalysis {
+    pub frame_count: usize,
+    pub timing: Timing,
+    pub games: Vec<Game>,
+}
+
+impl CachedAnalysis {
+    /// Serializes the analysis in a line-based text format tagged with [`ANALYSER_VERSION`].
+    /// Meetings, sabotages, deaths and task bar changes are listed after the game they belong to.
+    pub fn to_text(&self) -> String {
+        let mut text = format!(
+            "version {}\nframe_count {}\ninterval {}\nduration {}\n",
+            ANALYSER_VERSION, self.frame_count, self.timing.interval, self.timing.duration
+        );
+        for game in &self.games {
+            text.push_str(&format!(
+                "game {} {} {} {} {} {} {}\n",
+                game.frames.start,
+                game.frames.end,
+                game.start,
+                game.end,
+                if game.impostor { "impostor" } else { "crewmate" },
+                game.outcome.name(),
+                game.win_reason.name()
+            ));
+            for meeting in &game.meetings {
+                text.push_str(&format!(
+                    "meeting {} {} {} {} {} {}\n",
+                    meeting.frames.start,
+                    meeting.frames.end,
+                    meeting.start,
+                    meeting.end,
+                    meeting.ejection.name(),
+                    meeting.trigger.name()
+                ));
+            }
+            for sabotage in &game.sabotages {
+                text.push_str(&format!(
+                    "sabotage {} {} {} {}\n",
+                    sabotage.frames.start, sabotage.frames.end, sabotage.start, sabotage.end
+                ));
+            }
+            if let Some(death) = game.death {
+                text.push_str(&format!("death {} {}\n", death.frame, death.time));
+            }
+            for progress in &game.task_progress {
+                text.push_str(&format!("tasks {} {} {}\n", progress.frame, progress.time, progress.completion));
+            }
+        }
+        text
+    }
+
+    /// Parses the output of [`CachedAnalysis::to_text`].
+    /// Returns `None` if the text is invalid or was produced by another version of the analyser.
+    pub fn from_text(text: &str) -> Option<CachedAnalysis> {
+        let mut lines = text.lines();
+        let mut field = |name: &str| -> Option<String> {
+            let line = lines.next()?;
+            line.strip_prefix(name)?.strip_prefix(' ').map(|value| value.to_string())
+        };
+
+        if field("version")?.parse::<u32>().ok()? != ANALYSER_VERSION {
+            return None;
+        }
+        let frame_count = field("frame_count")?.parse().ok()?;
+        let interval = field("interval")?.parse().ok()?;
+        let duration = field("duration")?.parse().ok()?;
+
+        let mut games: Vec<Game> = Vec::new();
+        for line in lines {
+            if let Some(meeting) = line.strip_prefix("meeting ") {
+                let values: Vec<&str> = meeting.split(' ').collect();
+                if values.len() != 6 {
+                    return None;
+                }
+                games.last_mut()?.meetings.push(Meeting {
+                    frames: values[0].parse().ok()?..values[1].parse().ok()?,
+                    start: values[2].parse().ok()?,
+                    end: values[3].parse().ok()?,
+                    ejection: Ejection::from_name(values[4])?,
+                    trigger: MeetingTrigger::from_name(values[5])?,
+                });
+                continue;
+            }
+            if let Some(sabotage) = line.strip_prefix("sabotage ") {
+                let values: Vec<&str> = sabotage.split(' ').collect();
+                if values.len() != 4 {
+                    return None;
+                }
+                games.last_mut()?.sabotages.push(Sabotage {
+                    frames: values[0].parse().ok()?..values[1].parse().ok()?,
+                    start: values[2].parse().ok()?,
+                    end: values[3].parse().ok()?,
+                });
+                continue;
+            }
+            if let Some(death) = line.strip_prefix("death ") {
+                let values: Vec<&str> = death.split(' ').collect();
+                if values.len() != 2 {
+                    return None;
+                }
+                games.last_mut()?.death = Some(Death {
+                    frame: values[0].parse().ok()?,
+                    time: values[1].parse().ok()?,
+                });
+                continue;
+            }
+            if let Some(progress) = line.strip_prefix("tasks ") {
+                let values: Vec<&str> = progress.split(' ').collect();
+                if values.len() != 3 {
+                    return None;
+                }
+                games.last_mut()?.task_progress.push(TaskProgress {
+                    frame: values[0].parse().ok()?,
+                    time: values[1].parse().ok()?,
+                    completion: values[2].parse().ok()?,
+                });
+                continue;
+            }
+
+            let values: Vec<&str> = line.strip_prefix("game ")?.split(' ').collect();
+            if values.len() != 7 {
+                return None;
+            }
+            games.push(Game {
+                frames: values[0].parse().ok()?..values[1].parse().ok()?,
+                start: values[2].parse().ok()?,
+                end: values[3].parse().ok()?,
+                impostor: match values[4] {
+                    "impostor" => true,
+                    "crewmate" => false,
+                    _ => return None,
+                },
+                outcome: Outcome::from_name(values[5])?,
+                win_reason: WinReason::from_name(values[6])?,
+                meetings: Vec::new(),
+                sabotages: Vec::new(),
+                death: None,
+                task_progress: Vec::new(),
+            });
+        }
+
+        Some(CachedAnalysis {
+            frame_count,
+            timing: Timing { interval, duration },
+            games,
+        })
+    }
+}
+
+/// Waits for an IndexedDB request to complete and returns its result.
+async fn wait_request(request: &IdbRequest) -> Result<JsValue, JsValue> {
+    JsFuture::from(Promise::new(&mut |yes, no| {
+        request.add_event_listener_with_callback("success", &yes).unwrap();
+        request.add_event_listener_with_callback("error", &no).unwrap();
+    }))
+    .await?;
+    request.result()
+}
+
+async fn open_store(mode: IdbTransactionMode) -> Result<IdbObjectStore, JsValue> {
+    let request = window()
+        .unwrap()
+        .indexed_db()?
+        .ok_or_else(|| JsValue::from_str("IndexedDB is not available"))?
+        .open_with_u32(DATABASE_NAME, 1)?;
+
+    let request2 = request.clone();
+    let closure = Closure::wrap(Box::new(move |_event: Event| {
+        if let Some(database) = request2.result().ok().and_then(|db| db.dyn_into::<IdbDatabase>().ok()) {
+            database.create_object_store(STORE_NAME).unwrap();
+        }
+    }) as Box<dyn FnMut(_)>);
+    request.add_event_listener_with_callback("upgradeneeded", closure.as_ref().unchecked_ref())?;
+
+    let database: IdbDatabase = wait_request(&request).await?.dyn_into()?;
+    database
+        .transaction_with_str_and_mode(STORE_NAME, mode)?
+        .object_store(STORE_NAME)
+}
+
+/// Loads the analysis of a video, if it was cached by the current version of the analyser.
+pub async fn load(video_id: &str) -> Option<CachedAnalysis> {
+    let result = async {
+        let store = open_store(IdbTransactionMode::Readonly).await?;
+        wait_request(&store.get(&video_id.into())?).await
+    }
+    .await;
+
+    match result {
+        Ok(value) => CachedAnalysis::from_text(&value.as_string()?),
+        Err(e) => {
+            elog!("{}", AnalysisError::Cache(format!("{:?}", e)));
+            None
+        }
+    }
+}
+
+pub async fn store(video_id: &str, analysis: &CachedAnalysis) {
+    let result = async {
+        let store = open_store(IdbTransactionMode::Readwrite).await?;
+        wait_request(&store.put_with_key(&analysis.to_text().into(), &video_id.into())?).await
+    }
+    .await;
+
+    if let Err(e) = result {
+        elog!("{}", AnalysisError::Cache(format!("{:?}", e)));
+    }
+}
@@ -0,0 +1,252 @@
+//! Frame classification.
+//!
+//! Everything in this module is pure pixel math over raw RGBA buffers, so it
+//! runs (and is tested) natively as well as in the browser.
+
+pub const FRAME_WIDTH: u8 = 160;
+pub const FRAME_HEIGHT: u8 = 90;
+
+/// Columns of the task bar, which fills in green from the left
+const TASK_BAR: std::ops::Range<u8> = 2..71;
+
+/// Splits a decoded sprite sheet (RGBA, row-major) into its tiles, left to right then top to bottom.
+pub fn split_sheet(data: &[u8], columns: u8, rows: u8, tile_width: u8, tile_height: u8) -> Vec<Image> {
+    let sheet_width = columns as usize * tile_width as usize;
+    let mut images = Vec::new();
+
+    for row in 0..rows as usize {
+        for column in 0..columns as usize {
+            let mut tile = Vec::with_capacity(tile_width as usize * tile_height as usize * 4);
+            for y in 0..tile_height as usize {
+                let start = ((row * tile_height as usize + y) * sheet_width + column * tile_width as usize) * 4;
+                tile.extend_from_slice(&data[start..start + tile_width as usize * 4]);
+            }
+            images.push(Image::new(tile));
+        }
+    }
+
+    images
+}
+
+pub struct Image {
+    data: Vec<u8>,
+    pub council: bool,
+    pub bright_map: bool,
+    pub impostor_objective: bool,
+    pub open_map: bool,
+    pub game_settings: bool,
+    pub victory_screen: bool,
+    pub defeat_screen: bool,
+    pub alert: bool,
+    pub progress_bar: bool,
+    /// The "Impostor" splash shown when a game starts
+    pub impostor_reveal: bool,
+    /// The "Crewmate" splash shown when a game starts
+    pub crewmate_reveal: bool,
+    /// The screen shown after a vote, with its "... was ejected" caption.
+    /// Only the space scene of The Skeld is recognized: the sky of MIRA HQ, the lava of Polus
+    /// and the clouds of The Airship are too bright for the dark corners it relies on.
+    pub ejection: bool,
+    /// An ejection screen showing a player, as opposed to "No one was ejected"
+    pub player_ejected: bool,
+    /// The "Emergency Meeting" splash, a red flash with the meeting button
+    pub emergency_meeting: bool,
+    /// The "Dead Body Reported" splash, a red flash with the bones of the victim
+    pub body_reported: bool,
+    /// The red tint over the map during a reactor meltdown or an oxygen depletion
+    pub sabotage_alarm: bool,
+    /// Gameplay seen as a ghost, told by the "You are dead" text over the task list.
+    /// Never set for impostors, whose task list is written in red too.
+    pub ghost: bool,
+    /// Share of the crew's tasks done according to the task bar, from 0 to 1, when it is visible
+    pub task_completion: Option<f64>,
+    #[cfg(feature="debugging")]
+    base64: String,
+}
+
+impl Image {
+    /// Classifies a single frame. `data` must be a `FRAME_WIDTH`x`FRAME_HEIGHT` RGBA buffer.
+    pub fn new(data: Vec<u8>) -> Self {
+        assert_eq!(data.len(), FRAME_WIDTH as usize * FRAME_HEIGHT as usize * 4, "unexpected frame size");
+        let mut image = Image {
+            data,
+            council: false,
+            bright_map: false,
+            impostor_objective: false,
+            open_map: false,
+            game_settings: false,
+            victory_screen: false,
+            defeat_screen: false,
+            alert: false,
+            progress_bar: false,
+            impostor_reveal: false,
+            crewmate_reveal: false,
+            ejection: false,
+            player_ejected: false,
+            emergency_meeting: false,
+            body_reported: false,
+            sabotage_alarm: false,
+            ghost: false,
+            task_completion: None,
+            #[cfg(feature="debugging")]
+            base64: String::new(),
+        };
+
+        image.council = image.does_pixels_mean_match(28..74, 16..17, 0xadbfd4, 20);
+        image.bright_map = image.does_pixels_mean_match(152..156, 14..15, 0xc8cbcc, 20) && image.does_pixels_mean_match(153..155, 17..19, 0x54595a, 20);
+        image.impostor_objective = !image.council && image.does_pixels_mean_match(1..39, 12..13, 0x51252b, 20);
+        image.open_map = image.bright_map && (image.does_pixels_mean_match(24..29, 9..14, 0xbdc0c4, 20) || image.does_pixels_mean_match(10..15, 9..15, 0xb9bfbe, 20));
+        image.alert = {
+            let (r,g,b) = image.get_pixels_mean(0..160, 0..30);
+            let diff = r as i32 - (g as i32 + b as i32);
+            if r >= 105 && diff > -40 {
+                let (r,g,b) = image.get_pixels_mean(0..160, 30..60);
+                let diff = r as i32 - (g as i32 + b as i32);
+                if r >= 105 && diff > -40 {
+                    let (r,g,b) = image.get_pixels_mean(0..160, 60..90);
+                    let diff = r as i32 - (g as i32 + b as i32);
+                    r >= 105 && diff > -40
+                } else {
+                    false
+                }
+            } else {
+                false
+            }
+        };
+        // Both splashes are alerts, told apart by what is drawn in their center
+        image.emergency_meeting = image.alert && image.count_matching_pixels(40..120, 25..65, 0xf5c842, 50) >= 60;
+        image.body_reported = image.alert && !image.emergency_meeting && image.count_matching_pixels(40..120, 25..65, 0xe6e6e6, 30) >= 60;
+        image.progress_bar = !image.council && (image.does_pixels_mean_match(2..12, 3..6, 0x72a072, 20) || (image.does_pixels_mean_match(64..71, 3..6, 0x353d38, 20) && image.does_pixels_mean_match(64..71, 2..3, 0x989ca5, 50)));
+        if image.progress_bar {
+            let filled = TASK_BAR.filter(|&x| image.does_pixels_mean_match(x..x + 1, 3..6, 0x72a072, 30)).count();
+            image.task_completion = Some(filled as f64 / TASK_BAR.len() as f64);
+        }
+        // The alarm only tints the map, the HUD in the corners keeps its colors
+        image.sabotage_alarm = !image.council && !image.alert && (image.bright_map || image.progress_bar) && {
+            let (r, g, b) = image.get_pixels_mean(30..130, 25..80);
+            r >= 100 && r as i32 > g as i32 + 50 && r as i32 > b as i32 + 50
+        };
+        // The ghost tint and the missing buttons are too easily confused with the maps, only the text is used
+        image.ghost = (image.bright_map || image.progress_bar) && !image.impostor_objective && !image.council && !image.alert && !image.open_map && !image.sabotage_alarm
+            && image.count_matching_pixels(1..40, 14..20, 0xff4d4d, 50) >= 10;
+        image.game_settings = !image.is_game() && image.does_pixels_mean_match(1..17, 3..68, 0x484949, 15);
+        image.victory_screen = !image.is_game() && image.does_pixels_mean_match(49..111, 12..21, 0x163150, 16) && image.does_pixels_mean_match(40..120, 25..41, 0x000000, 10);
+        image.defeat_screen = !image.is_game() && image.does_pixels_mean_match(53..105, 9..23, 0x470c10, 10) && image.does_pixels_mean_match(40..120, 25..41, 0x090807, 10);
+        // The role title is written over a black background, wider than the "Defeat" title which has the same red
+        let role_reveal = !image.is_game() && !image.victory_screen && !image.defeat_screen && image.does_pixels_mean_match(0..20, 30..90, 0x000000, 30) && image.does_pixels_mean_match(140..160, 30..90, 0x000000, 30);
+        image.impostor_reveal = role_reveal && image.count_matching_pixels(30..50, 6..26, 0xff1919, 60) >= 8 && image.count_matching_pixels(110..130, 6..26, 0xff1919, 60) >= 8;
+        image.crewmate_reveal = role_reveal && image.count_matching_pixels(30..50, 6..26, 0x8cffff, 60) >= 8 && image.count_matching_pixels(110..130, 6..26, 0x8cffff, 60) >= 8;
+        // A white caption in the middle of a dark screen. The ejected player is the only colorful thing around it.
+        // This only holds in space, the other maps eject players over bright scenes.
+        // A red or cyan player can also pass for a role splash, the segmenter tells them apart from the meeting before.
+        image.ejection = !image.is_game() && !image.victory_screen && !image.defeat_screen && image.does_pixels_mean_match(0..30, 0..20, 0x000000, 40) && image.does_pixels_mean_match(130..160, 70..90, 0x000000, 40) && image.count_matching_pixels(30..130, 38..52, 0xffffff, 40) >= 20;
+        image.player_ejected = image.ejection && image.count_saturated_pixels(0..160, 0..38, 100) + image.count_saturated_pixels(0..160, 52..90, 100) >= 20;
+
+        #[cfg(feature="debugging")]
+        {
+            use image::{ImageBuffer, RgbImage};
+            let mut img: RgbImage = ImageBuffer::new(160, 90);
+            for x in 0..160 {
+                for y in 0..90 {
+                    let (r, g, b) = image.get_pixel(x, y);
+                    img.put_pixel(x as u32, y as u32, image::Rgb([r, g, b]));
+                }
+            }
+            let mut output = Vec::new();
+            let encoder = image::codecs::png::PngEncoder::new(&mut output);
+            encoder
+                .encode(&img, 160, 90, image::ColorType::Rgb8)
+                .unwrap();
+            image.base64 = base64::encode(output);
+        }
+
+        image
+    }
+
+    pub fn get_pixel(&self, x: u8, y: u8) -> (u8, u8, u8) {
+        let offset = (y as usize * FRAME_WIDTH as usize + x as usize) * 4;
+        (
+            self.data[offset],
+            self.data[offset + 1],
+            self.data[offset + 2],
+        )
+    }
+
+    pub fn get_pixels_mean(&self, x_range: std::ops::Range<u8>, y_range: std::ops::Range<u8>) -> (u8, u8, u8) {
+        let mut r: u64 = 0;
+        let mut g: u64 = 0;
+        let mut b: u64 = 0;
+
+        for x in x_range.clone() {
+            for y in y_range.clone() {
+                let (r2, g2, b2) = self.get_pixel(x, y);
+                r += r2 as u64;
+                g += g2 as u64;
+                b += b2 as u64;
+            }
+        }
+
+        let number = (x_range.end - x_range.start) as u64 * (y_range.end - y_range.start) as u64;
+        r /= number;
+        g /= number;
+        b /= number;
+
+        (r as u8, g as u8, b as u8)
+    }
+
+    pub fn does_pixels_mean_match(&self, x_range: std::ops::Range<u8>, y_range: std::ops::Range<u8>, expected: u32, tolerance: u8) -> bool {
+        let [_, expected_r, expected_g, expected_b] = expected.to_be_bytes();
+        let got = self.get_pixels_mean(x_range, y_range);
+        std::cmp::max(got.0, expected_r) - std::cmp::min(got.0, expected_r) <= tolerance
+            && std::cmp::max(got.1, expected_g) - std::cmp::min(got.1, expected_g) <= tolerance
+            && std::cmp::max(got.2, expected_b) - std::cmp::min(got.2, expected_b) <= tolerance
+    }
+
+    pub fn does_pixel_match(&self, x: u8, y: u8, expected: u32, tolerance: u8) -> bool {
+        let [_, expected_r, expected_g, expected_b] = expected.to_be_bytes();
+        let got = self.get_pixel(x, y);
+        std::cmp::max(got.0, expected_r) - std::cmp::min(got.0, expected_r) <= tolerance
+            && std::cmp::max(got.1, expected_g) - std::cmp::min(got.1, expected_g) <= tolerance
+            && std::cmp::max(got.2, expected_b) - std::cmp::min(got.2, expected_b) <= tolerance
+    }
+
+    /// Number of pixels in the area that match a color, for sparse features like text
+    pub fn count_matching_pixels(&self, x_range: std::ops::Range<u8>, y_range: std::ops::Range<u8>, expected: u32, tolerance: u8) -> usize {
+        let mut count = 0;
+        for x in x_range {
+            for y in y_range.clone() {
+                if self.does_pixel_match(x, y, expected, tolerance) {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    /// Number of pixels in the area whose channels differ by at least `min_saturation`
+    pub fn count_saturated_pixels(&self, x_range: std::ops::Range<u8>, y_range: std::ops::Range<u8>, min_saturation: u8) -> usize {
+        let mut count = 0;
+        for x in x_range {
+            for y in y_range.clone() {
+                let (r, g, b) = self.get_pixel(x, y);
+                if r.max(g).max(b) - r.min(g).min(b) >= min_saturation {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    #[cfg(feature="debugging")]
+    pub fn base64(&self) -> &str {
+        &self.base64
+    }
+
+    pub fn is_black(&self) -> bool {
+        self.data.chunks(4).all(|pixel| pixel[..3] == [0, 0, 0])
+    }
+
+    pub fn is_game(&self) -> bool {
+        self.bright_map || self.council || self.alert || self.progress_bar
+    }
+}
@@ -0,0 +1,50 @@
+use std::fmt;
+
+/// Everything that can go wrong while analysing a video.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AnalysisError {
+    /// The watch page or the `pbj` response does not have the expected structure.
+    PageParsing(&'static str),
+    /// The identity token could not be read from YouTube's IndexedDB.
+    IdentityToken(&'static str),
+    /// A request could not be sent or its body could not be read.
+    Network(String),
+    /// A request was answered with a non-200 status.
+    UnexpectedStatus(u16),
+    /// The storyboard spec is missing or malformed.
+    Storyboard(&'static str),
+    /// A sprite sheet could not be decoded.
+    ImageDecoding(&'static str),
+    /// The frames cannot be classified.
+    Classification(&'static str),
+    /// The analysis cache could not be read or written.
+    Cache(String),
+}
+
+impl AnalysisError {
+    /// Whether trying again later could succeed
+    pub fn is_transient(&self) -> bool {
+        match self {
+            AnalysisError::Network(_) => true,
+            AnalysisError::UnexpectedStatus(status) => *status == 429 || *status >= 500,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for AnalysisError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnalysisError::PageParsing(e) => write!(f, "Failed to parse the page: {}", e),
+            AnalysisError::IdentityToken(e) => write!(f, "Failed to get the identity token: {}", e),
+            AnalysisError::Network(e) => write!(f, "Network error: {}", e),
+            AnalysisError::UnexpectedStatus(status) => write!(f, "Unexpected response status: {}", status),
+            AnalysisError::Storyboard(e) => write!(f, "Invalid storyboard: {}", e),
+            AnalysisError::ImageDecoding(e) => write!(f, "Failed to decode a storyboard image: {}", e),
+            AnalysisError::Classification(e) => write!(f, "Failed to classify frames: {}", e),
+            AnalysisError::Cache(e) => write!(f, "Cache error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for AnalysisError {}
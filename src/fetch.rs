@@ -0,0 +1,97 @@
+//! HTTP transport used by the analysis.
+//!
+//! The rest of the crate only talks to the network through [`Fetcher`], so the browser
+//! implementation can be swapped for another transport or for in-memory fixtures.
+
+use crate::error::AnalysisError;
+use std::{cell::RefCell, collections::HashMap, future::Future, pin::Pin};
+
+pub struct HttpResponse {
+    pub status: u16,
+    pub body: Vec<u8>,
+}
+
+pub type FetchFuture<'a> = Pin<Box<dyn Future<Output = Result<HttpResponse, AnalysisError>> + 'a>>;
+
+pub trait Fetcher {
+    /// Sends a GET request. Non-200 statuses are not errors at this level.
+    fn fetch<'a>(&'a self, url: &'a str, headers: &'a [(&'a str, &'a str)]) -> FetchFuture<'a>;
+}
+
+/// Sends requests with `window.fetch`.
+pub struct BrowserFetcher;
+
+impl Fetcher for BrowserFetcher {
+    fn fetch<'a>(&'a self, url: &'a str, headers: &'a [(&'a str, &'a str)]) -> FetchFuture<'a> {
+        use wasm_bindgen_futures::JsFuture;
+        use web_sys::*;
+
+        Box::pin(async move {
+            let network_error = |e| AnalysisError::Network(format!("{:?}", e));
+
+            let mut request: RequestInit = RequestInit::new();
+            request.method("GET");
+            let request_headers = Headers::new().unwrap();
+            for (name, value) in headers {
+                request_headers.append(name, value).map_err(network_error)?;
+            }
+            request.headers(&request_headers);
+
+            let response = Response::from(
+                JsFuture::from(window().unwrap().fetch_with_str_and_init(url, &request))
+                    .await
+                    .map_err(network_error)?,
+            );
+            let body = JsFuture::from(response.array_buffer().map_err(network_error)?)
+                .await
+                .map_err(network_error)?;
+
+            Ok(HttpResponse {
+                status: response.status(),
+                body: js_sys::Uint8Array::new(&body).to_vec(),
+            })
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+}
+
+/// Answers requests from a fixed set of responses. Unknown urls get a 404.
+///
+/// Requests are recorded so that tests can check what was sent.
+#[derive(Default)]
+pub struct MemoryFetcher {
+    responses: HashMap<String, (u16, Vec<u8>)>,
+    requests: RefCell<Vec<RecordedRequest>>,
+}
+
+impl MemoryFetcher {
+    pub fn new() -> MemoryFetcher {
+        MemoryFetcher::default()
+    }
+
+    pub fn add_response(&mut self, url: impl Into<String>, status: u16, body: impl Into<Vec<u8>>) {
+        self.responses.insert(url.into(), (status, body.into()));
+    }
+
+    /// The requests received so far
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests.borrow().clone()
+    }
+}
+
+impl Fetcher for MemoryFetcher {
+    fn fetch<'a>(&'a self, url: &'a str, headers: &'a [(&'a str, &'a str)]) -> FetchFuture<'a> {
+        self.requests.borrow_mut().push(RecordedRequest {
+            url: url.to_string(),
+            headers: headers.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect(),
+        });
+        let (status, body) = self.responses.get(url).cloned().unwrap_or((404, Vec::new()));
+
+        Box::pin(async move { Ok(HttpResponse { status, body }) })
+    }
+}
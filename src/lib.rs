@@ -1,18 +1,41 @@
-use std::{cell::Cell, ops::AddAssign, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
 
 use wasm_bindgen::{prelude::*, JsCast};
-use wasm_bindgen_futures::JsFuture;
 use web_sys::*;
 #[macro_use]
 mod util;
-mod ytimg;
-mod yt_format;
+pub mod analysis;
+pub mod cache;
+pub mod classifier;
+pub mod error;
+pub mod fetch;
+pub mod ytimg;
+pub mod yt_format;
 mod rendering;
 mod settings;
+pub mod storage;
+use analysis::{Game, Timing};
+use error::AnalysisError;
+use fetch::{BrowserFetcher, Fetcher};
 use yt_format::*;
 use rendering::*;
 
-pub async fn get_images(loaded: bool) -> Option<(Vec<(std::ops::Range<usize>, bool)>, usize)> {
+/// Whether the page still shows the video with this id, as YouTube navigates between videos without reloading.
+fn is_current_video(id: &str) -> bool {
+    window()
+        .and_then(|window| window.location().href().ok())
+        .and_then(|url| get_video_id(&url))
+        .as_deref()
+        == Some(id)
+}
+
+pub async fn get_images(loaded: bool, fetcher: &dyn Fetcher) -> Result<Option<(Vec<Game>, Timing)>, AnalysisError> {
+    let id = get_video_id(&window().unwrap().location().href().unwrap())
+        .ok_or(AnalysisError::PageParsing("no video id in the url"))?;
+
     let (yt_initial_player_response, yt_initial_data) = if loaded {
         let document = window()
             .unwrap()
@@ -21,173 +44,157 @@ pub async fn get_images(loaded: bool) -> Option<(Vec<(std::ops::Range<usize>, bo
             .document_element()
             .unwrap();
         let html = js_sys::Reflect::get(&document, &"innerHTML".into())
-            .unwrap()
-            .as_string()
-            .unwrap();
+            .ok()
+            .and_then(|html| html.as_string())
+            .ok_or(AnalysisError::PageParsing("cannot read the page html"))?;
 
-        let idx_start = html.find("var ytInitialPlayerResponse = {").unwrap() + 30;
+        let idx_start = html
+            .find("var ytInitialPlayerResponse = {")
+            .ok_or(AnalysisError::PageParsing("ytInitialPlayerResponse not found"))?
+            + 30;
         let idx_end = html[idx_start..]
             .find(";var meta = document.createElement('meta');")
-            .unwrap();
+            .ok_or(AnalysisError::PageParsing("end of ytInitialPlayerResponse not found"))?;
         let yt_initial_player_response = html[idx_start..idx_start + idx_end].to_string();
-        let yt_initial_player_response = parse_json(yt_initial_player_response).unwrap();
+        let yt_initial_player_response = parse_json(yt_initial_player_response)
+            .ok_or(AnalysisError::PageParsing("ytInitialPlayerResponse is not valid json"))?;
 
-        let idx_start = html.find("var ytInitialData = {").unwrap() + 20;
+        let idx_start = html
+            .find("var ytInitialData = {")
+            .ok_or(AnalysisError::PageParsing("ytInitialData not found"))?
+            + 20;
         let idx_end = html[idx_start..]
             .find(";</script><")
-            .unwrap();
+            .ok_or(AnalysisError::PageParsing("end of ytInitialData not found"))?;
         let yt_initial_data = html[idx_start..idx_start + idx_end].to_string();
-        let yt_initial_data = parse_json(yt_initial_data).unwrap();
+        let yt_initial_data = parse_json(yt_initial_data)
+            .ok_or(AnalysisError::PageParsing("ytInitialData is not valid json"))?;
 
         (yt_initial_player_response, yt_initial_data)
     } else {
         remove_previous_display().await;
-        let mut url = window().unwrap().location().href().unwrap();
-        let start = url.find("watch?v=").unwrap();
-        url.replace_range(..start + 8, "");
-        let end = url.find('&').unwrap_or_else(|| url.len());
-        url.truncate(end);
-        let id = url;
 
         let open_db_request = window()
             .unwrap()
             .indexed_db()
-            .unwrap()
-            .unwrap()
+            .ok()
+            .flatten()
+            .ok_or(AnalysisError::IdentityToken("IndexedDB is not available"))?
             .open("swpushnotificationsdb")
-            .unwrap();
+            .map_err(|_| AnalysisError::IdentityToken("cannot open swpushnotificationsdb"))?;
         let open_db_request2 = open_db_request.clone();
         let state = Rc::new(Cell::new(None));
         let state2 = Rc::clone(&state);
-        let closure = Closure::wrap(Box::new(move |_event: web_sys::Event| {
-            let database: IdbDatabase = open_db_request2.result().unwrap().dyn_into().unwrap();
+        let closure = Closure::wrap(Box::new(move |event: web_sys::Event| {
+            let database = match event.type_().as_str() {
+                "success" => open_db_request2
+                    .result()
+                    .ok()
+                    .and_then(|database| database.dyn_into::<IdbDatabase>().ok())
+                    .ok_or(AnalysisError::IdentityToken("invalid swpushnotificationsdb")),
+                _ => Err(AnalysisError::IdentityToken("cannot open swpushnotificationsdb")),
+            };
             state2.set(Some(database));
         }) as Box<dyn FnMut(_)>);
-        open_db_request
-            .add_event_listener_with_callback(
-                "success", // TODO HANDLE ERROR
-                closure.as_ref().unchecked_ref(),
-            )
-            .unwrap();
+        for event in &["success", "error"] {
+            open_db_request
+                .add_event_listener_with_callback(event, closure.as_ref().unchecked_ref())
+                .unwrap();
+        }
 
         let db = loop {
             match state.take() {
-                Some(db) => break db,
+                Some(db) => break db?,
                 None => util::sleep(std::time::Duration::from_millis(200)).await,
             }
         };
-        let transaction = db.transaction_with_str("swpushnotificationsstore").unwrap();
-        let store = transaction
-            .object_store("swpushnotificationsstore")
-            .unwrap();
-        let value_request = store.get(&"IDToken".into()).unwrap();
+        let store = db
+            .transaction_with_str("swpushnotificationsstore")
+            .and_then(|transaction| transaction.object_store("swpushnotificationsstore"))
+            .map_err(|_| AnalysisError::IdentityToken("swpushnotificationsstore not found"))?;
+        let value_request = store
+            .get(&"IDToken".into())
+            .map_err(|_| AnalysisError::IdentityToken("cannot read IDToken"))?;
         let value_request2 = value_request.clone();
         let state = Rc::new(Cell::new(None));
         let state2 = Rc::clone(&state);
-        let closure = Closure::wrap(Box::new(move |_event: web_sys::Event| {
-            let value = js_sys::Reflect::get(&value_request2.result().unwrap(), &"value".into())
-                .unwrap()
-                .as_string()
-                .unwrap();
+        let closure = Closure::wrap(Box::new(move |event: web_sys::Event| {
+            let value = match event.type_().as_str() {
+                "success" => value_request2
+                    .result()
+                    .ok()
+                    .and_then(|result| js_sys::Reflect::get(&result, &"value".into()).ok())
+                    .and_then(|value| value.as_string())
+                    .ok_or(AnalysisError::IdentityToken("no IDToken stored (are you logged in?)")),
+                _ => Err(AnalysisError::IdentityToken("cannot read IDToken")),
+            };
             state2.set(Some(value));
         }) as Box<dyn FnMut(_)>);
-        value_request
-            .add_event_listener_with_callback(
-                "success", // TODO HANDLE ERROR
-                closure.as_ref().unchecked_ref(),
-            )
-            .unwrap();
+        for event in &["success", "error"] {
+            value_request
+                .add_event_listener_with_callback(event, closure.as_ref().unchecked_ref())
+                .unwrap();
+        }
 
         let user_id = loop {
             match state.take() {
-                Some(user_id) => break user_id,
+                Some(user_id) => break user_id?,
                 None => util::sleep(std::time::Duration::from_millis(200)).await,
             }
         };
 
-        let mut request: RequestInit = RequestInit::new();
-        request.method("GET");
-
-        let headers = Headers::new().unwrap();
-        headers
-            .append("X-SPF-Previous", "https://www.youtube.com/")
-            .unwrap();
-        headers
-            .append("X-SPF-Referer", "https://www.youtube.com/")
-            .unwrap();
-        headers.append("X-YouTube-Client-Name", "1").unwrap();
-        headers
-            .append("X-YouTube-Client-Version", "2.20210110.08.00")
-            .unwrap();
-        headers.append("x-youtube-csoc", "1").unwrap();
-        headers
-            .append(
-                "X-YouTube-Device",
-                "cbr=Firefox&cbrver=84.0&ceng=Gecko&cengver=84.0&cos=X11&cplatform=DESKTOP",
-            )
-            .unwrap();
-        headers
-            .append("X-Youtube-Identity-Token", &user_id)
-            .unwrap();
-        headers
-            .append("X-YouTube-Time-Zone", "Europe/Paris")
-            .unwrap();
-        headers.append("X-YouTube-Utc-Offset", "60").unwrap();
-        request.headers(&headers);
-
-        let window = window().unwrap();
-        let response = Response::from(
-            JsFuture::from(window.fetch_with_str_and_init(
-                &format!("https://www.youtube.com/watch?v={}&pbj=1", id),
-                &request,
-            ))
-            .await
-            .unwrap(),
-        );
-
-        if response.status() != 200 {
-            panic!("Unexpected response status");
-        }
-
-        let object = JsFuture::from(response.json().unwrap()).await.unwrap();
-        let mut yt_initial_player_response = js_sys::Reflect::get(&object, &2.into()).unwrap();
-        yt_initial_player_response = js_sys::Reflect::get(&yt_initial_player_response, &"playerResponse".into()).unwrap();
-
-        let mut yt_initial_data: JsValue = js_sys::Reflect::get(&object, &3.into()).ok()?;
-        yt_initial_data = js_sys::Reflect::get(&yt_initial_data, &"response".into()).ok()?;
+        let object = parse_json(fetch_watch_data(fetcher, &id, &user_id).await?)
+            .ok_or(AnalysisError::PageParsing("the pbj response is not valid json"))?;
+        let yt_initial_player_response = js_sys::Reflect::get(&object, &2.into())
+            .and_then(|value| js_sys::Reflect::get(&value, &"playerResponse".into()))
+            .map_err(|_| AnalysisError::PageParsing("playerResponse not found"))?;
+        let yt_initial_data = js_sys::Reflect::get(&object, &3.into())
+            .and_then(|value| js_sys::Reflect::get(&value, &"response".into()))
+            .map_err(|_| AnalysisError::PageParsing("response not found"))?;
 
         (yt_initial_player_response, yt_initial_data)
     };
 
     if get_game_name(&yt_initial_data) != Some("Among Us".to_string()) {
         log!("cancelled {:?}", yt_initial_data);
-        return None;
+        return Ok(None);
+    }
+    if let Some(cached) = cache::load(&id).await {
+        log!("Using the cached analysis of {}", id);
+        return Ok(Some((cached.games, cached.timing)));
     }
-    let endpoints = ytimg::parse_value(get_storyboard(yt_initial_player_response).unwrap()).unwrap();
+    let video_duration = get_video_duration(&yt_initial_player_response);
+    let endpoints = ytimg::parse_value(
+        get_storyboard(yt_initial_player_response).ok_or(AnalysisError::Storyboard("no storyboard spec"))?,
+    )?;
 
     log!("Status confirmed: {:?}", endpoints);
 
     display_loading_state().await;
-    let endpoint = &endpoints[2];
-
-    let mut images = Vec::new();
-    let mut n = 0;
-    while let Ok(mut new_images) = endpoint.get_image(n).await {
-        util::sleep(std::time::Duration::from_millis(100)).await;
-        images.append(&mut new_images);
-        n += 1;
+    let endpoint = endpoints
+        .get(2)
+        .ok_or(AnalysisError::Storyboard("missing the third storyboard level"))?;
+    if endpoint.image_width() != classifier::FRAME_WIDTH || endpoint.image_height() != classifier::FRAME_HEIGHT {
+        return Err(AnalysisError::Classification("unsupported storyboard frame size"));
     }
-    'images: for i in (0..images.len()).into_iter().rev() {
-        for x in 0..160 {
-            for y in 0..90 {
-                if images[i].get_pixel(x, y) != (0, 0, 0) {
-                    break 'images;
-                }
+    let timing = endpoint.timing(video_duration)?;
+
+    let segmenter = RefCell::new(analysis::Segmenter::new(timing));
+    let mut images = endpoint
+        .get_all_images(fetcher, &ytimg::DownloadOptions::default(), &|new_images, _, _| {
+            let mut segmenter = segmenter.borrow_mut();
+            for image in new_images {
+                segmenter.push(image);
             }
-        }
-        images.remove(i);
-        log!("removed an image");
-    }
+            // The bar now belongs to another video
+            if !is_current_video(&id) {
+                return;
+            }
+            display_partial_bar(timing.duration, segmenter.games(), timing.frame_time(segmenter.analysed_frames()));
+        })
+        .await?;
+    let removed = analysis::trim_black_frames(&mut images);
+    log!("removed {} black images", removed);
 
     let selection = [
         112, 113, 114, 115, 116, 117, 247, 248, 249, 250, 335, 336, 337,
@@ -208,60 +215,45 @@ pub async fn get_images(loaded: bool) -> Option<(Vec<(std::ops::Range<usize>, bo
     b /= selection.len() as u64;
     log!("mean = {} {} {}", r, g, b);
 
-    let mut games = Vec::new();
-    let mut current_game: Option<(usize, usize, usize)> = None;
-    for (idx, image) in images.iter().enumerate() {
-        if image.is_game() && current_game.is_none() {
-            current_game = Some((idx, 0, 1));
-        } else if image.victory_screen || image.game_settings || image.defeat_screen {
-            if let Some((start, impostor_objectives_count, ingame_frames_count)) = current_game {
-                let ratio = impostor_objectives_count as f64 / ingame_frames_count as f64;
-                games.push((start..idx, ratio > 0.6));
-                current_game = None;
-            }
-        }
-
-        if let Some((_, impostor_objectives_count, _)) = &mut current_game {
-            if image.impostor_objective && !image.alert {
-                impostor_objectives_count.add_assign(1);
-            }
-        }
-
-        if let Some((_, _, ingame_frames_count)) = &mut current_game {
-            if image.is_game() && !image.open_map && !image.council && !image.alert {
-                ingame_frames_count.add_assign(1);
-            }
-        }
-    }
-    if let Some((start, impostor_objectives_count, ingame_frames_count)) = current_game {
-        let ratio = impostor_objectives_count as f64 / ingame_frames_count as f64;
-        games.push((start..images.len(), ratio > 0.6));
-    }
-
-    let mut too_short_games = Vec::new();
-    for (idx, (range, _)) in games.iter().enumerate() {
-        if range.end - range.start <= 2 {
-            too_short_games.insert(0, idx);
-        }
-    }
-    for idx in too_short_games {
-        games.remove(idx);
+    let games = segmenter.into_inner().finish();
+    cache::store(
+        &id,
+        &cache::CachedAnalysis {
+            frame_count: endpoint.frame_count(),
+            timing,
+            games: games.clone(),
+        },
+    )
+    .await;
+    if !is_current_video(&id) {
+        log!("{} is not displayed anymore", id);
+        return Ok(None);
     }
 
     #[cfg(feature = "debugging")]
     display_debugging_data(&images, &games);
 
-    Some((games, images.len()))
+    Ok(Some((games, timing)))
 }
 
 pub async fn run(loaded: bool) {
     log!("running...");
-    let (games, lenght) = match get_images(loaded).await {
-        Some(v) => v,
-        None => return,
+    let id = get_video_id(&window().unwrap().location().href().unwrap());
+    let (games, timing) = match get_images(loaded, &BrowserFetcher).await {
+        Ok(Some(v)) => v,
+        Ok(None) => return,
+        Err(e) => {
+            elog!("{}", e);
+            // The error would replace the bar of the video the user moved to
+            if id.as_deref().map(is_current_video).unwrap_or(false) {
+                display_error(&e).await;
+            }
+            return;
+        }
     };
 
-    display_bar(lenght, games).await;
+    display_comments(&games).await;
+    display_bar(timing.duration, games).await;
 }
 
 #[wasm_bindgen(start)]
@@ -302,4 +294,10 @@ pub async fn main() {
     }) as Box<dyn FnMut(_)>);
     window().unwrap().document().unwrap().add_event_listener_with_callback("fullscreenchange", closure.as_ref().unchecked_ref()).unwrap();
     closure.forget();
+
+    let closure = Closure::wrap(Box::new(move |event: KeyboardEvent| {
+        handle_shortcut(&event);
+    }) as Box<dyn FnMut(_)>);
+    window().unwrap().add_event_listener_with_callback("keydown", closure.as_ref().unchecked_ref()).unwrap();
+    closure.forget();
 }
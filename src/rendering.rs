@@ -1,6 +1,19 @@
-use crate::{settings::*, util::sleep, ytimg::Image};
-use maud::{PreEscaped, Render};
-use std::{ops::Range, rc::Rc};
+use crate::{
+    analysis::{
+        death_revealed, format_timestamp, next_game, outcome_revealed, previous_game, role_revealed, skip_to,
+        task_completion, timestamp_summary, Ejection, Game, Meeting, MeetingTrigger, Outcome, Sabotage, WinReason,
+    },
+    error::AnalysisError,
+    settings::*,
+    util::sleep,
+};
+#[cfg(feature = "debugging")]
+use crate::classifier::Image;
+use maud::{Markup, PreEscaped, Render};
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
 use wasm_bindgen::{prelude::*, JsCast};
 use web_sys::*;
 
@@ -27,11 +40,792 @@ impl Choice for Theme {
     }
 
     fn select_value(s: &str) -> Self {
-        Theme::Default
+        match s {
+            "Light" => Theme::Light,
+            "Dark" => Theme::Dark,
+            _ => Theme::Default,
+        }
+    }
+}
+
+/// Seconds skipped when seeking to a game, e.g. to skip the role reveal
+#[derive(Clone, Copy, PartialEq)]
+struct SeekOffset(u8);
+
+impl std::fmt::Display for SeekOffset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            0 => write!(f, "None"),
+            seconds => write!(f, "{} seconds", seconds),
+        }
+    }
+}
+
+impl Choice for SeekOffset {
+    fn enumerate_values() -> Vec<Self> {
+        [0, 5, 10, 15].iter().map(|&seconds| SeekOffset(seconds)).collect()
+    }
+
+    fn select_value(s: &str) -> Self {
+        SeekOffset(s.trim_end_matches(" seconds").parse().unwrap_or(0))
+    }
+}
+
+/// Key bindings to jump between games
+#[derive(Clone, Copy, PartialEq)]
+enum Shortcuts {
+    Off,
+    Brackets,
+    AltArrows,
+}
+
+impl std::fmt::Display for Shortcuts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Shortcuts::Off => write!(f, "Off"),
+            Shortcuts::Brackets => write!(f, "[ ] and {{ }}"),
+            Shortcuts::AltArrows => write!(f, "Alt + arrows"),
+        }
+    }
+}
+
+impl Choice for Shortcuts {
+    fn enumerate_values() -> Vec<Self> {
+        vec![Shortcuts::Off, Shortcuts::Brackets, Shortcuts::AltArrows]
+    }
+
+    fn select_value(s: &str) -> Self {
+        match s {
+            "Off" => Shortcuts::Off,
+            "Alt + arrows" => Shortcuts::AltArrows,
+            _ => Shortcuts::Brackets,
+        }
+    }
+}
+
+struct Jump {
+    forward: bool,
+    impostor_only: bool,
+}
+
+impl Shortcuts {
+    /// The jump bound to a key press, if any.
+    /// With brackets, `[`/`]` jump between games and `{`/`}` between impostor games.
+    /// With arrows, Shift restricts the jump to impostor games.
+    fn jump(self, event: &KeyboardEvent) -> Option<Jump> {
+        let key = event.key();
+        let (forward, impostor_only) = match self {
+            Shortcuts::Off => return None,
+            Shortcuts::Brackets if event.ctrl_key() || event.alt_key() || event.meta_key() => return None,
+            Shortcuts::Brackets => match key.as_str() {
+                "]" => (true, false),
+                "[" => (false, false),
+                "}" => (true, true),
+                "{" => (false, true),
+                _ => return None,
+            },
+            Shortcuts::AltArrows if !event.alt_key() || event.ctrl_key() || event.meta_key() => return None,
+            Shortcuts::AltArrows => match key.as_str() {
+                "ArrowRight" => (true, event.shift_key()),
+                "ArrowLeft" => (false, event.shift_key()),
+                _ => return None,
+            },
+        };
+        Some(Jump {
+            forward,
+            impostor_only,
+        })
+    }
+}
+
+/// Which parts of the games are hidden until playback reaches them
+#[derive(Clone, Copy, PartialEq)]
+enum Spoilers {
+    Shown,
+    HideRoles,
+    HideRolesAndOutcomes,
+}
+
+impl std::fmt::Display for Spoilers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Spoilers::Shown => write!(f, "Off"),
+            Spoilers::HideRoles => write!(f, "Hide roles"),
+            Spoilers::HideRolesAndOutcomes => write!(f, "Hide roles and outcomes"),
+        }
+    }
+}
+
+impl Choice for Spoilers {
+    fn enumerate_values() -> Vec<Self> {
+        vec![Spoilers::Shown, Spoilers::HideRoles, Spoilers::HideRolesAndOutcomes]
+    }
+
+    fn select_value(s: &str) -> Self {
+        match s {
+            "Hide roles" => Spoilers::HideRoles,
+            "Hide roles and outcomes" => Spoilers::HideRolesAndOutcomes,
+            _ => Spoilers::Shown,
+        }
+    }
+}
+
+impl Spoilers {
+    fn hides_role(self, game: &Game, time: f64) -> bool {
+        self != Spoilers::Shown && !role_revealed(game, time)
+    }
+
+    fn hides_outcome(self, game: &Game, time: f64) -> bool {
+        self.hides_role(game, time) || (self == Spoilers::HideRolesAndOutcomes && !outcome_revealed(game, time))
+    }
+
+    fn hides_death(self, game: &Game, time: f64) -> bool {
+        self.hides_role(game, time) || (self == Spoilers::HideRolesAndOutcomes && !death_revealed(game, time))
+    }
+
+    /// Number of roles, outcomes and deaths shown at `time`, to know when the chapters must be re-rendered
+    fn revealed_count(self, games: &[Game], time: f64) -> usize {
+        games
+            .iter()
+            .map(|game| {
+                !self.hides_role(game, time) as usize
+                    + !self.hides_outcome(game, time) as usize
+                    + !self.hides_death(game, time) as usize
+            })
+            .sum()
+    }
+}
+
+impl Theme {
+    /// The class applied to the chapter bar. `Default` follows YouTube's own dark mode.
+    fn class(self) -> &'static str {
+        let dark = match self {
+            Theme::Default => window()
+                .and_then(|window| window.document())
+                .and_then(|document| document.document_element())
+                .map(|html| html.has_attribute("dark"))
+                .unwrap_or(false),
+            Theme::Light => false,
+            Theme::Dark => true,
+        };
+        if dark {
+            "dark_theme"
+        } else {
+            "light_theme"
+        }
+    }
+}
+
+/// The settings shown in the addon's menu. They are created once and live for the whole page.
+struct AddonSettings {
+    generate_comments: &'static CheckBox<&'static str>,
+    theme: &'static Selection<Theme>,
+    seek_offset: &'static Selection<SeekOffset>,
+    shortcuts: &'static Selection<Shortcuts>,
+    auto_skip: &'static CheckBox<&'static str>,
+    spoilers: &'static Selection<Spoilers>,
+    menu: Rc<Settings>,
+}
+
+/// What the chapter bar currently shows
+struct DisplayedBar {
+    duration: f64,
+    games: Vec<Game>,
+    /// Equal to `duration` once the whole video is analysed
+    analysed_until: f64,
+    /// `Spoilers::revealed_count` when the bar was rendered
+    revealed_count: usize,
+}
+
+thread_local! {
+    static ADDON_SETTINGS: &'static AddonSettings = Box::leak(Box::new(AddonSettings::new()));
+    static DISPLAYED_BAR: RefCell<DisplayedBar> = const {
+        RefCell::new(DisplayedBar {
+            duration: 0.0,
+            games: Vec::new(),
+            analysed_until: 0.0,
+            revealed_count: 0,
+        })
+    };
+    /// Incremented every time a feedback message is shown, so that only the last one is hidden
+    static FEEDBACK_GENERATION: Cell<u32> = const { Cell::new(0) };
+}
+
+impl AddonSettings {
+    fn new() -> AddonSettings {
+        let generate_comments = Box::leak(Box::new(CheckBox::new(
+            "amgus_ext_comments",
+            "Generate comments",
+            true,
+        )));
+        let theme = Box::leak(Box::new(Selection::new(
+            "amgus_ext_theme",
+            "Theme",
+            Theme::Default,
+        )));
+        let seek_offset = Box::leak(Box::new(Selection::new(
+            "amgus_ext_seek_offset",
+            "Skip at game start",
+            SeekOffset(0),
+        )));
+        let shortcuts = Box::leak(Box::new(Selection::new(
+            "amgus_ext_shortcuts",
+            "Keyboard shortcuts",
+            Shortcuts::Brackets,
+        )));
+        let auto_skip = Box::leak(Box::new(CheckBox::new(
+            "amgus_ext_auto_skip",
+            "Play only impostor games",
+            false,
+        )));
+        let spoilers = Box::leak(Box::new(Selection::new(
+            "amgus_ext_spoilers",
+            "Spoiler-free mode",
+            Spoilers::Shown,
+        )));
+        let mut menu = Settings::new();
+        menu.add_setting(generate_comments);
+        menu.add_setting(theme);
+        menu.add_setting(seek_offset);
+        menu.add_setting(shortcuts);
+        menu.add_setting(auto_skip);
+        menu.add_setting(spoilers);
+
+        generate_comments.on_change(|_| show_comments());
+        theme.on_change(|_| apply_theme());
+        auto_skip.on_change(|_| self::auto_skip());
+        spoilers.on_change(|_| {
+            render_bar();
+            show_comments();
+        });
+        observe_youtube_theme();
+
+        AddonSettings {
+            generate_comments,
+            theme,
+            seek_offset,
+            shortcuts,
+            auto_skip,
+            spoilers,
+            menu: Rc::new(menu),
+        }
+    }
+
+    fn get() -> &'static AddonSettings {
+        ADDON_SETTINGS.with(|settings| *settings)
+    }
+}
+
+/// Restyles the chapter bar with the selected theme.
+fn apply_theme() {
+    let chapters = window()
+        .unwrap()
+        .document()
+        .unwrap()
+        .get_element_by_id("among_us_addon_chapters");
+    if let Some(chapters) = chapters {
+        chapters
+            .set_attribute("class", AddonSettings::get().theme.selected().class())
+            .unwrap();
+    }
+}
+
+/// Re-applies the theme when YouTube switches between light and dark mode.
+fn observe_youtube_theme() {
+    let html = match window().unwrap().document().unwrap().document_element() {
+        Some(html) => html,
+        None => return,
+    };
+    let closure = Closure::wrap(Box::new(move |_: js_sys::Array, _: MutationObserver| {
+        apply_theme();
+    }) as Box<dyn FnMut(js_sys::Array, MutationObserver)>);
+    let observer = MutationObserver::new(closure.as_ref().unchecked_ref()).unwrap();
+    closure.forget();
+
+    let mut config = MutationObserverInit::new();
+    config
+        .attributes(true)
+        .attribute_filter(&js_sys::Array::of1(&JsValue::from_str("dark")));
+    observer.observe_with_options(&html, &config).unwrap();
+}
+
+/// Renders the chapters, hiding what has not been watched yet at `time` in spoiler-free mode.
+fn render_chapters(duration: f64, games: &[Game], analysed_until: Option<f64>, time: f64) -> Markup {
+    let factor: f64 = 100.0 / duration;
+    let spoilers = AddonSettings::get().spoilers.selected();
+
+    maud::html! {
+        style { (PreEscaped(include_str!("integrated.css"))) }
+        #among_us_addon_chapters class=(AddonSettings::get().theme.selected().class()) {
+            @for (idx, game) in games.iter().enumerate() {
+                @let style = format!("left: {}%; width: calc({}% - 4px);", game.start * factor, (game.end - game.start) * factor);
+                @let tooltip = chapter_tooltip(idx, game, spoilers, time);
+                @let outcome = if spoilers.hides_outcome(game, time) { Outcome::Unknown } else { game.outcome };
+                @if spoilers.hides_role(game, time) {
+                    div.hidden_game.flex_font style=(style) title=(tooltip) data-start=(game.start) data-end=(game.end) {
+                        (format!("Game {}", idx + 1))
+                    }
+                } @else if game.impostor {
+                    div.impostor_game.flex_font.win[outcome == Outcome::Win].loss[outcome == Outcome::Loss] style=(style) title=(tooltip) data-start=(game.start) data-end=(game.end) {
+                        "Impostor" (outcome_mark(outcome))
+                        (ghost_shading(game, spoilers, time))
+                        (task_gauge(game, spoilers, time))
+                    }
+                } @else {
+                    div.crewmate_game.flex_font.win[outcome == Outcome::Win].loss[outcome == Outcome::Loss] style=(style) title=(tooltip) data-start=(game.start) data-end=(game.end) {
+                        "Crewmate" (outcome_mark(outcome))
+                        (ghost_shading(game, spoilers, time))
+                        (task_gauge(game, spoilers, time))
+                    }
+                }
+            }
+            // The meetings and sabotages of a game would give away how it unfolds
+            @for game in games.iter().filter(|game| !spoilers.hides_role(game, time)) {
+                @for (idx, meeting) in game.meetings.iter().enumerate() {
+                    div.timeline_marker.meeting_marker
+                        style=(format!("left: {}%; width: {}%;", meeting.start * factor, (meeting.end - meeting.start) * factor))
+                        title=(meeting_tooltip(idx, meeting, game, spoilers, time))
+                        data-start=(meeting.start) {}
+                }
+                @for (idx, sabotage) in game.sabotages.iter().enumerate() {
+                    div.timeline_marker.sabotage_marker
+                        style=(format!("left: {}%; width: {}%;", sabotage.start * factor, (sabotage.end - sabotage.start) * factor))
+                        title=(sabotage_tooltip(idx, sabotage))
+                        data-start=(sabotage.start) {}
+                }
+            }
+            @if let Some(analysed_until) = analysed_until {
+                div#among_us_addon_progress.flex_font style=(format!("left: {}%; width: calc({}% - 4px);", analysed_until * factor, (duration - analysed_until) * factor)) {
+                    (format!("Analysing... {}%", (analysed_until * factor) as u8))
+                }
+            }
+        }
+    }
+}
+
+fn outcome_mark(outcome: Outcome) -> &'static str {
+    match outcome {
+        Outcome::Win => " \u{2713}",
+        Outcome::Loss => " \u{2717}",
+        Outcome::Unknown => "",
+    }
+}
+
+/// Shades the part of a chapter spent as a ghost, so that viewers can skip it.
+fn ghost_shading(game: &Game, spoilers: Spoilers, time: f64) -> Markup {
+    maud::html! {
+        @if let Some(death) = game.death.filter(|_| !spoilers.hides_death(game, time)) {
+            div.ghost_segment style=(format!("left: {}%;", (death.time - game.start) * 100.0 / (game.end - game.start))) {}
+        }
+    }
+}
+
+/// Shows how far the task bar got at the end of a game, to see how close the crewmates were to a task win.
+fn task_gauge(game: &Game, spoilers: Spoilers, time: f64) -> Markup {
+    maud::html! {
+        @if let Some(completion) = task_completion(game).filter(|_| !spoilers.hides_outcome(game, time)) {
+            div.task_gauge style=(format!("width: {}%;", completion * 100.0)) {}
+        }
+    }
+}
+
+fn meeting_tooltip(idx: usize, meeting: &Meeting, game: &Game, spoilers: Spoilers, time: f64) -> String {
+    let mut tooltip = format!(
+        "Meeting {} \u{2014} {} ({})",
+        idx + 1,
+        format_timestamp(meeting.start),
+        format_timestamp(meeting.end - meeting.start)
+    );
+    match meeting.trigger {
+        MeetingTrigger::EmergencyButton => tooltip.push_str(" \u{2014} Emergency meeting"),
+        MeetingTrigger::BodyReport => tooltip.push_str(" \u{2014} Body reported"),
+        MeetingTrigger::Unknown => (),
+    }
+    if !spoilers.hides_outcome(game, time) {
+        match meeting.ejection {
+            Ejection::Player => tooltip.push_str(" \u{2014} Player ejected"),
+            Ejection::NoOne => tooltip.push_str(" \u{2014} No one ejected"),
+            Ejection::Unknown => (),
+        }
+    }
+    tooltip
+}
+
+fn sabotage_tooltip(idx: usize, sabotage: &Sabotage) -> String {
+    format!(
+        "Sabotage {} \u{2014} {} ({})",
+        idx + 1,
+        format_timestamp(sabotage.start),
+        format_timestamp(sabotage.end - sabotage.start)
+    )
+}
+
+fn chapter_tooltip(idx: usize, game: &Game, spoilers: Spoilers, time: f64) -> String {
+    let mut tooltip = format!("Game {}", idx + 1);
+    if !spoilers.hides_role(game, time) {
+        tooltip.push_str(if game.impostor { " \u{2014} Impostor" } else { " \u{2014} Crewmate" });
+    }
+    if !spoilers.hides_outcome(game, time) {
+        match game.outcome {
+            Outcome::Win => tooltip.push_str(" \u{2014} Win"),
+            Outcome::Loss => tooltip.push_str(" \u{2014} Loss"),
+            Outcome::Unknown => (),
+        }
+        match game.win_reason {
+            WinReason::Tasks => tooltip.push_str(" (tasks)"),
+            WinReason::Votes => tooltip.push_str(" (votes)"),
+            WinReason::Unknown => (),
+        }
+        if let Some(completion) = task_completion(game) {
+            tooltip.push_str(&format!(" \u{2014} Tasks {}%", (completion * 100.0).round()));
+        }
+    }
+    if let Some(death) = game.death.filter(|_| !spoilers.hides_death(game, time)) {
+        tooltip.push_str(&format!(" \u{2014} Died at {}", format_timestamp(death.time)));
+    }
+    tooltip.push_str(&format!(" \u{2014} {}", format_timestamp(game.end - game.start)));
+    tooltip
+}
+
+/// The video element of the player
+fn video() -> Option<HtmlMediaElement> {
+    window()?
+        .document()?
+        .query_selector("#movie_player video")
+        .ok()??
+        .dyn_into()
+        .ok()
+}
+
+/// Seeks the video to the start of a game, skipping the configured offset.
+fn seek_to_game(start: f64, end: f64) {
+    if let Some(video) = video() {
+        let offset = AddonSettings::get().seek_offset.selected().0 as f64;
+        video.set_current_time(f64::min(start + offset, end));
+    }
+}
+
+/// Jumps to the next or previous game when a shortcut is pressed.
+pub fn handle_shortcut(event: &KeyboardEvent) {
+    let jump = match AddonSettings::get().shortcuts.selected().jump(event) {
+        Some(jump) => jump,
+        None => return,
+    };
+    // Don't steal keys from the search box or the comment fields
+    let typing = event
+        .target()
+        .and_then(|target| target.dyn_into::<HtmlElement>().ok())
+        .map(|target| target.is_content_editable() || ["INPUT", "TEXTAREA"].contains(&target.tag_name().as_str()))
+        .unwrap_or(false);
+    let video = match video() {
+        Some(video) if !typing => video,
+        _ => return,
+    };
+    event.prevent_default();
+
+    let time = video.current_time() - AddonSettings::get().seek_offset.selected().0 as f64;
+    let target = DISPLAYED_BAR.with(|bar| {
+        let games = &bar.borrow().games;
+        let target = if jump.forward {
+            next_game(games, time, jump.impostor_only)
+        } else {
+            previous_game(games, time, jump.impostor_only)
+        };
+        target.map(|(idx, game)| (idx, game.clone()))
+    });
+    match target {
+        Some((idx, game)) => {
+            seek_to_game(game.start, game.end);
+            let spoilers = AddonSettings::get().spoilers.selected();
+            show_feedback(&chapter_tooltip(idx, &game, spoilers, video.current_time()));
+        }
+        None => show_feedback(match (jump.forward, jump.impostor_only) {
+            (true, false) => "No next game",
+            (false, false) => "No previous game",
+            (true, true) => "No next impostor game",
+            (false, true) => "No previous impostor game",
+        }),
+    }
+}
+
+/// Briefly shows a message over the video.
+fn show_feedback(message: &str) {
+    let document = window().unwrap().document().unwrap();
+    let feedback = match document.get_element_by_id("among_us_addon_feedback") {
+        Some(feedback) => feedback,
+        None => {
+            let movie_player = match document.get_element_by_id("movie_player") {
+                Some(movie_player) => movie_player,
+                None => return,
+            };
+            let feedback = document.create_element("div").unwrap();
+            feedback.set_id("among_us_addon_feedback");
+            movie_player.append_child(&feedback).unwrap();
+            feedback
+        }
+    };
+    feedback.set_text_content(Some(message));
+    feedback.class_list().add_1("visible").unwrap();
+
+    let generation = FEEDBACK_GENERATION.with(|generation| {
+        generation.set(generation.get() + 1);
+        generation.get()
+    });
+    wasm_bindgen_futures::spawn_local(async move {
+        sleep(std::time::Duration::from_millis(1200)).await;
+        if FEEDBACK_GENERATION.with(|current| current.get()) == generation {
+            feedback.class_list().remove_1("visible").unwrap();
+        }
+    });
+}
+
+/// Seeks past everything but impostor games when auto-skip is enabled.
+fn auto_skip() {
+    if !AddonSettings::get().auto_skip.checked() {
+        return;
+    }
+    let video = match video() {
+        Some(video) => video,
+        None => return,
+    };
+    let target = DISPLAYED_BAR.with(|bar| {
+        let bar = bar.borrow();
+        skip_to(&bar.games, video.current_time(), bar.analysed_until, bar.duration)
+    });
+    if let Some(target) = target {
+        let game = DISPLAYED_BAR.with(|bar| {
+            bar.borrow()
+                .games
+                .iter()
+                .enumerate()
+                .find(|(_, game)| game.start == target)
+                .map(|(idx, game)| (idx, game.clone()))
+        });
+        match game {
+            Some((idx, game)) => {
+                seek_to_game(game.start, game.end);
+                let spoilers = AddonSettings::get().spoilers.selected();
+                show_feedback(&format!("Skipped to {}", chapter_tooltip(idx, &game, spoilers, video.current_time())));
+            }
+            None => {
+                video.set_current_time(target);
+                show_feedback("No impostor game left");
+            }
+        }
+    }
+}
+
+/// Re-renders the chapter bar when playback reaches the start or the end of a game.
+fn reveal_spoilers() {
+    let spoilers = AddonSettings::get().spoilers.selected();
+    if spoilers == Spoilers::Shown {
+        return;
+    }
+    let time = match video() {
+        Some(video) => video.current_time(),
+        None => return,
+    };
+    let changed = DISPLAYED_BAR.with(|bar| {
+        let bar = bar.borrow();
+        spoilers.revealed_count(&bar.games, time) != bar.revealed_count
+    });
+    if changed {
+        render_bar();
+    }
+}
+
+/// Reacts to the playback position of the video. Registered once per video element.
+fn listen_playback() {
+    let video = match video() {
+        Some(video) => video,
+        None => return,
+    };
+    if video.has_attribute("data-among-us-listening") {
+        return;
+    }
+    video.set_attribute("data-among-us-listening", "").unwrap();
+
+    let closure = Closure::wrap(Box::new(move |_: Event| {
+        auto_skip();
+        reveal_spoilers();
+    }) as Box<dyn FnMut(_)>);
+    video
+        .add_event_listener_with_callback("timeupdate", closure.as_ref().unchecked_ref())
+        .unwrap();
+    closure.forget();
+}
+
+/// Seeks to a game when its chapter is clicked, or to a meeting or sabotage when its marker is clicked,
+/// instead of letting the progress bar handle the click.
+/// The listeners are registered once per container, chapters being re-rendered inside it.
+fn listen_chapter_clicks(container: &Element) {
+    if container.has_attribute("data-among-us-listening") {
+        return;
+    }
+    container.set_attribute("data-among-us-listening", "").unwrap();
+
+    fn clicked_chapter(event: &Event) -> Option<Element> {
+        let target: Element = event.target()?.dyn_into().ok()?;
+        target.closest(".impostor_game, .crewmate_game, .hidden_game, .timeline_marker").ok()?
+    }
+
+    let closure = Closure::wrap(Box::new(move |event: Event| {
+        if clicked_chapter(&event).is_some() {
+            event.stop_propagation();
+            event.prevent_default();
+        }
+    }) as Box<dyn FnMut(_)>);
+    container
+        .add_event_listener_with_callback("mousedown", closure.as_ref().unchecked_ref())
+        .unwrap();
+    closure.forget();
+
+    let closure = Closure::wrap(Box::new(move |event: Event| {
+        if let Some(chapter) = clicked_chapter(&event) {
+            event.stop_propagation();
+            let time = |name| chapter.get_attribute(name).and_then(|value| value.parse().ok());
+            if chapter.class_list().contains("timeline_marker") {
+                if let (Some(start), Some(video)) = (time("data-start"), video()) {
+                    video.set_current_time(start);
+                }
+            } else if let (Some(start), Some(end)) = (time("data-start"), time("data-end")) {
+                seek_to_game(start, end);
+            }
+        }
+    }) as Box<dyn FnMut(_)>);
+    container
+        .add_event_listener_with_callback("click", closure.as_ref().unchecked_ref())
+        .unwrap();
+    closure.forget();
+}
+
+/// Renders the chapter bar described by `DISPLAYED_BAR` at the current playback position.
+fn render_bar() {
+    let container = match window()
+        .unwrap()
+        .document()
+        .unwrap()
+        .get_elements_by_class_name("ytp-progress-bar-padding")
+        .item(0)
+    {
+        Some(container) => container,
+        None => return,
+    };
+    let time = video().map(|video| video.current_time()).unwrap_or(0.0);
+    let html = DISPLAYED_BAR.with(|bar| {
+        let mut bar = bar.borrow_mut();
+        if bar.duration <= 0.0 {
+            return None;
+        }
+        bar.revealed_count = AddonSettings::get().spoilers.selected().revealed_count(&bar.games, time);
+        let analysed_until = Some(bar.analysed_until).filter(|&until| until < bar.duration);
+        Some(render_chapters(bar.duration, &bar.games, analysed_until, time))
+    });
+    if let Some(html) = html {
+        container.set_inner_html(&html.into_string());
+        listen_chapter_clicks(&container);
+        listen_playback();
+        update_flex_font();
     }
 }
 
-pub async fn display_bar(lenght: usize, games: Vec<(Range<usize>, bool)>) {
+fn set_displayed_bar(duration: f64, games: Vec<Game>, analysed_until: f64) {
+    DISPLAYED_BAR.with(|bar| {
+        *bar.borrow_mut() = DisplayedBar {
+            duration,
+            games,
+            analysed_until,
+            revealed_count: 0,
+        }
+    });
+}
+
+/// Shows the games found so far while the rest of the video is being analysed.
+pub fn display_partial_bar(duration: f64, games: &[Game], analysed_until: f64) {
+    set_displayed_bar(duration, games.to_vec(), analysed_until);
+    render_bar();
+}
+
+/// Whether the comments panel is shown. It lists every role and outcome, so spoiler-free mode hides it.
+fn comments_visible() -> bool {
+    let settings = AddonSettings::get();
+    settings.generate_comments.checked() && settings.spoilers.selected() == Spoilers::Shown
+}
+
+/// Shows or hides the comments panel according to the settings.
+fn show_comments() {
+    let panel = window()
+        .unwrap()
+        .document()
+        .unwrap()
+        .get_element_by_id("among_us_addon_comments");
+    if let Some(panel) = panel {
+        panel.toggle_attribute_with_force("hidden", !comments_visible()).unwrap();
+    }
+}
+
+/// Adds a panel under the video with the list of games, ready to be pasted as a comment.
+pub async fn display_comments(games: &[Game]) {
+    let window = window().unwrap();
+    let document = window.document().unwrap();
+    if let Some(previous) = document.get_element_by_id("among_us_addon_comments") {
+        previous.remove();
+    }
+    if games.is_empty() {
+        return;
+    }
+    let below = loop {
+        match document.query_selector("ytd-watch-flexy #below").unwrap() {
+            Some(below) => break below,
+            None => sleep(std::time::Duration::from_millis(200)).await,
+        }
+    };
+
+    let summary = timestamp_summary(games);
+    let panel = document.create_element("div").unwrap();
+    below.insert_before(&panel, below.first_child().as_ref()).unwrap();
+    panel.set_outer_html(&maud::html! {
+        #among_us_addon_comments hidden[!comments_visible()] {
+            .among_us_addon_comments_header {
+                span { "Among Us games" }
+                button#among_us_addon_copy { "Copy" }
+            }
+            textarea readonly rows=(games.len()) { (summary) }
+        }
+    }.into_string());
+
+    let button = document.get_element_by_id("among_us_addon_copy").unwrap();
+    let textarea: HtmlTextAreaElement = document
+        .query_selector("#among_us_addon_comments>textarea")
+        .unwrap()
+        .unwrap()
+        .dyn_into()
+        .unwrap();
+    let button2 = button.clone();
+    let closure = Closure::wrap(Box::new(move |_: Event| {
+        textarea.select();
+        let copied = web_sys::window()
+            .unwrap()
+            .document()
+            .unwrap()
+            .dyn_into::<HtmlDocument>()
+            .unwrap()
+            .exec_command("copy")
+            .unwrap_or(false);
+        button2.set_text_content(Some(if copied { "Copied!" } else { "Copy failed" }));
+        let button = button2.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            sleep(std::time::Duration::from_millis(1500)).await;
+            button.set_text_content(Some("Copy"));
+        });
+    }) as Box<dyn FnMut(_)>);
+    button
+        .add_event_listener_with_callback("click", closure.as_ref().unchecked_ref())
+        .unwrap();
+    closure.forget();
+}
+
+pub async fn display_bar(duration: f64, games: Vec<Game>) {
     // select the target node
     let target = window()
         .unwrap()
@@ -96,36 +890,15 @@ pub async fn display_bar(lenght: usize, games: Vec<(Range<usize>, bool)>) {
 
     let window = window().unwrap();
     let document = window.document().unwrap();
-    let container = loop {
-        match document
-            .get_elements_by_class_name("ytp-progress-bar-padding")
-            .item(0)
-        {
-            Some(container) => break container,
-            None => sleep(std::time::Duration::from_millis(200)).await,
-        }
-    };
-    let factor: f64 = 100.0 / lenght as f64;
-
-    let html = maud::html! {
-        style { (PreEscaped(include_str!("integrated.css"))) }
-        #among_us_addon_chapters {
-            @for (game, is_impostor) in games.iter() {
-                @if *is_impostor {
-                    div.impostor_game.flex_font style=(format!("left: {}%; width: calc({}% - 4px);", game.start as f64 * factor, (game.end - game.start) as f64 * factor)) {
-                        "Impostor"
-                    }
-                } @else {
-                    div.crewmate_game.flex_font style=(format!("left: {}%; width: calc({}% - 4px);", game.start as f64 * factor, (game.end - game.start) as f64 * factor)) {
-                        "Crewmate"
-                    }
-                }
-
-            }
-        }
-    };
-    container.set_inner_html(&html.into_string());
-    update_flex_font();
+    while document
+        .get_elements_by_class_name("ytp-progress-bar-padding")
+        .item(0)
+        .is_none()
+    {
+        sleep(std::time::Duration::from_millis(200)).await;
+    }
+    set_displayed_bar(duration, games, duration);
+    render_bar();
 
     if document
         .query_selector("#among_us_settings_menu")
@@ -147,22 +920,9 @@ pub async fn display_bar(lenght: usize, games: Vec<(Range<usize>, bool)>) {
     among_us_settings_menu
         .set_attribute("style", "display: none;")
         .unwrap();
-    let generate_comments_setting = Box::leak(Box::new(CheckBox::new(
-        "amgus_ext_comments",
-        "Generate comments",
-        true,
-    )));
-    let theme_setting = Box::leak(Box::new(Selection::new(
-        "amgus_ext_theme",
-        "Theme",
-        Theme::Default,
-    )));
-    let mut settings = Settings::new();
-    settings.add_setting(generate_comments_setting);
-    settings.add_setting(theme_setting);
-    among_us_settings_menu.set_inner_html(&settings.render().into_string());
+    let settings_rc = Rc::clone(&AddonSettings::get().menu);
+    among_us_settings_menu.set_inner_html(&settings_rc.render().into_string());
     movie_player.append_child(&among_us_settings_menu).unwrap();
-    let settings_rc = Rc::new(settings);
 
     // Create the button in the bottom bar
     let ytp_right_controls = document
@@ -213,8 +973,10 @@ pub async fn display_bar(lenght: usize, games: Vec<(Range<usize>, bool)>) {
                 element.set_inner_html(&settings_rc.render().into_string());
                 Settings::enable(settings_rc);
 
+                element.set_attribute("style", "width: 349px;").unwrap();
+                let height = std::cmp::min(element.first_element_child().unwrap().scroll_height(), 700);
                 element
-                    .set_attribute("style", "width: 349px; height: 177px;")
+                    .set_attribute("style", &format!("width: 349px; height: {}px;", height))
                     .unwrap();
                 sleep(std::time::Duration::from_millis(10)).await;
                 element.remove_attribute("aria-hidden").unwrap();
@@ -333,6 +1095,10 @@ pub async fn remove_previous_display() {
         }
     };
     container.set_inner_html("");
+    set_displayed_bar(0.0, Vec::new(), 0.0);
+    if let Some(comments) = window.document().unwrap().get_element_by_id("among_us_addon_comments") {
+        comments.remove();
+    }
 }
 
 pub async fn display_loading_state() {
@@ -358,8 +1124,31 @@ pub async fn display_loading_state() {
     container.set_inner_html(&html.into_string());
 }
 
+pub async fn display_error(error: &AnalysisError) {
+    let window = window().unwrap();
+    let container = loop {
+        match window
+            .document()
+            .unwrap()
+            .get_elements_by_class_name("ytp-progress-bar-padding")
+            .item(0)
+        {
+            Some(container) => break container,
+            None => sleep(std::time::Duration::from_millis(100)).await,
+        }
+    };
+
+    let html = maud::html! {
+        style { (PreEscaped(include_str!("integrated.css"))) }
+        #among_us_addon_error {
+            "Among Us Youtube Extension : " (error)
+        }
+    };
+    container.set_inner_html(&html.into_string());
+}
+
 #[cfg(feature = "debugging")]
-pub fn display_debugging_data(images: &[Image], games: &[(Range<usize>, bool)]) {
+pub fn display_debugging_data(images: &[Image], games: &[Game]) {
     let html = maud::html! {
         head {
             title { "Video Analys Report" }
@@ -438,6 +1227,68 @@ pub fn display_debugging_data(images: &[Image], games: &[(Range<usize>, bool)])
                                     title=(format!("Mean of 2..12,3..6 = {:?}\nMean of 64..71,3..6 = {:?}\nMean of 64..71,2..3 = {:?}", image.get_pixels_mean(2..12, 3..6), image.get_pixels_mean(64..71, 3..6), image.get_pixels_mean(64..71, 2..3)))
                                     {(image.progress_bar)}
                             }
+                            tr {
+                                td {"impostor reveal"}
+                                td
+                                    boolean_value=(image.impostor_reveal)
+                                    title=(format!("Red pixels in 30..50,6..26 = {}\nRed pixels in 110..130,6..26 = {}", image.count_matching_pixels(30..50, 6..26, 0xff1919, 60), image.count_matching_pixels(110..130, 6..26, 0xff1919, 60)))
+                                    {(image.impostor_reveal)}
+                            }
+                            tr {
+                                td {"crewmate reveal"}
+                                td
+                                    boolean_value=(image.crewmate_reveal)
+                                    title=(format!("Cyan pixels in 30..50,6..26 = {}\nCyan pixels in 110..130,6..26 = {}", image.count_matching_pixels(30..50, 6..26, 0x8cffff, 60), image.count_matching_pixels(110..130, 6..26, 0x8cffff, 60)))
+                                    {(image.crewmate_reveal)}
+                            }
+                            tr {
+                                td {"ejection"}
+                                td
+                                    boolean_value=(image.ejection)
+                                    title=(format!("Mean of 0..30,0..20 = {:?}\nMean of 130..160,70..90 = {:?}\nWhite pixels in 30..130,38..52 = {}", image.get_pixels_mean(0..30, 0..20), image.get_pixels_mean(130..160, 70..90), image.count_matching_pixels(30..130, 38..52, 0xffffff, 40)))
+                                    {(image.ejection)}
+                            }
+                            tr {
+                                td {"player ejected"}
+                                td
+                                    boolean_value=(image.player_ejected)
+                                    title=(format!("Saturated pixels in 0..160,0..38 = {}\nSaturated pixels in 0..160,52..90 = {}", image.count_saturated_pixels(0..160, 0..38, 100), image.count_saturated_pixels(0..160, 52..90, 100)))
+                                    {(image.player_ejected)}
+                            }
+                            tr {
+                                td {"emergency meeting"}
+                                td
+                                    boolean_value=(image.emergency_meeting)
+                                    title=(format!("Yellow pixels in 40..120,25..65 = {}", image.count_matching_pixels(40..120, 25..65, 0xf5c842, 50)))
+                                    {(image.emergency_meeting)}
+                            }
+                            tr {
+                                td {"body reported"}
+                                td
+                                    boolean_value=(image.body_reported)
+                                    title=(format!("Bone pixels in 40..120,25..65 = {}", image.count_matching_pixels(40..120, 25..65, 0xe6e6e6, 30)))
+                                    {(image.body_reported)}
+                            }
+                            tr {
+                                td {"sabotage alarm"}
+                                td
+                                    boolean_value=(image.sabotage_alarm)
+                                    title=(format!("Mean of 30..130,25..80 = {:?}", image.get_pixels_mean(30..130, 25..80)))
+                                    {(image.sabotage_alarm)}
+                            }
+                            tr {
+                                td {"ghost"}
+                                td
+                                    boolean_value=(image.ghost)
+                                    title=(format!("Red text pixels in 1..40,14..20 = {}", image.count_matching_pixels(1..40, 14..20, 0xff4d4d, 50)))
+                                    {(image.ghost)}
+                            }
+                            tr {
+                                td {"task completion"}
+                                td
+                                    title=(format!("Mean of 2..12,3..6 = {:?}\nMean of 64..71,3..6 = {:?}", image.get_pixels_mean(2..12, 3..6), image.get_pixels_mean(64..71, 3..6)))
+                                    {(image.task_completion.map(|completion| format!("{:.0}%", completion * 100.0)).unwrap_or_else(|| "-".to_string()))}
+                            }
                         }
                     }
                 }
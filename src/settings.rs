@@ -1,24 +1,42 @@
-use crate::util::sleep;
+use crate::{storage::StoredSettings, util::sleep};
 use maud::{html, Markup, Render};
-use std::{cell::Cell, fmt::Display, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    fmt::Display,
+    rc::Rc,
+};
 use wasm_bindgen::{prelude::*, JsCast};
 use web_sys::*;
 
 pub struct Settings {
     settings: Vec<&'static dyn Setting>,
+    stored: RefCell<StoredSettings>,
 }
 
 impl Settings {
+    /// Creates an empty menu, backed by the values saved in local storage.
     pub fn new() -> Settings {
         Settings {
             settings: Vec::new(),
+            stored: RefCell::new(StoredSettings::load()),
         }
     }
 
+    /// Adds a setting to the menu and restores its saved value, if any.
     pub fn add_setting(&mut self, setting: &'static dyn Setting) {
+        if let Some(value) = self.stored.borrow().get(setting.id()) {
+            setting.restore(value);
+        }
         self.settings.push(setting)
     }
 
+    /// Saves the new value of a setting.
+    pub fn persist(&self, setting: &dyn Setting) {
+        let mut stored = self.stored.borrow_mut();
+        stored.set(setting.id(), setting.value());
+        stored.save();
+    }
+
     pub fn enable(self_rc: Rc<Settings>) {
         for setting in &self_rc.settings {
             setting.enable(Rc::clone(&self_rc))
@@ -41,13 +59,21 @@ impl Render for Settings {
 }
 
 pub trait Setting: Render {
-    fn enable(&self, settings: Rc<Settings>);
+    fn enable(&'static self, settings: Rc<Settings>);
+    fn id(&self) -> &'static str;
+    /// The current value, as saved in local storage
+    fn value(&self) -> String;
+    /// Sets the value from its saved form. Unknown values are ignored.
+    fn restore(&self, value: &str);
 }
 
+type Listener<V> = Box<dyn Fn(V)>;
+
 pub struct CheckBox<T: Display> {
     id: &'static str,
     label: T,
     checked: Rc<Cell<bool>>,
+    listeners: RefCell<Vec<Listener<bool>>>,
 }
 
 impl<T: Display> CheckBox<T> {
@@ -56,6 +82,22 @@ impl<T: Display> CheckBox<T> {
             id,
             label,
             checked: Rc::new(Cell::new(checked)),
+            listeners: RefCell::new(Vec::new()),
+        }
+    }
+
+    pub fn checked(&self) -> bool {
+        self.checked.get()
+    }
+
+    /// Calls `listener` whenever the user toggles the checkbox.
+    pub fn on_change(&self, listener: impl Fn(bool) + 'static) {
+        self.listeners.borrow_mut().push(Box::new(listener));
+    }
+
+    fn notify(&self) {
+        for listener in self.listeners.borrow().iter() {
+            listener(self.checked.get());
         }
     }
 }
@@ -75,7 +117,21 @@ impl<T: Display> Render for CheckBox<T> {
 }
 
 impl<T: Display> Setting for CheckBox<T> {
-    fn enable(&self, _settings: Rc<Settings>) {
+    fn id(&self) -> &'static str {
+        self.id
+    }
+
+    fn value(&self) -> String {
+        self.checked.get().to_string()
+    }
+
+    fn restore(&self, value: &str) {
+        if let Ok(checked) = value.parse() {
+            self.checked.set(checked);
+        }
+    }
+
+    fn enable(&'static self, settings: Rc<Settings>) {
         let window = window().unwrap();
         let document = window.document().unwrap();
         let self_element = document
@@ -93,6 +149,8 @@ impl<T: Display> Setting for CheckBox<T> {
             self_element2
                 .set_attribute("aria-checked", &state.get().to_string())
                 .unwrap();
+            settings.persist(self);
+            self.notify();
         }) as Box<dyn FnMut(_)>);
         self_element
             .add_event_listener_with_callback("click", closure.as_ref().unchecked_ref())
@@ -110,6 +168,7 @@ pub struct Selection<C: Choice> {
     id: &'static str,
     label: String,
     selected: Rc<Cell<C>>,
+    listeners: RefCell<Vec<Listener<C>>>,
 }
 
 impl<C: Choice> Selection<C> {
@@ -118,6 +177,22 @@ impl<C: Choice> Selection<C> {
             id,
             label: label.to_string(),
             selected: Rc::new(Cell::new(selected)),
+            listeners: RefCell::new(Vec::new()),
+        }
+    }
+
+    pub fn selected(&self) -> C {
+        self.selected.get()
+    }
+
+    /// Calls `listener` whenever the user selects a value.
+    pub fn on_change(&self, listener: impl Fn(C) + 'static) {
+        self.listeners.borrow_mut().push(Box::new(listener));
+    }
+
+    fn notify(&self) {
+        for listener in self.listeners.borrow().iter() {
+            listener(self.selected.get());
         }
     }
 }
@@ -135,7 +210,23 @@ impl<C: Choice> Render for Selection<C> {
 }
 
 impl<C: Choice + 'static> Setting for Selection<C> {
-    fn enable(&self, settings: Rc<Settings>) {
+    fn id(&self) -> &'static str {
+        self.id
+    }
+
+    fn value(&self) -> String {
+        self.selected.get().to_string()
+    }
+
+    fn restore(&self, value: &str) {
+        // `select_value` falls back to a default, which must not replace the current choice
+        let choice = C::select_value(value);
+        if choice.to_string() == value {
+            self.selected.set(choice);
+        }
+    }
+
+    fn enable(&'static self, settings: Rc<Settings>) {
         let window = window().unwrap();
         let document = window.document().unwrap();
         let self_element = document.query_selector(&format!(
@@ -150,16 +241,19 @@ impl<C: Choice + 'static> Setting for Selection<C> {
             node: Node,
             value: C,
             settings: Rc<Settings>,
+            setting: &'static Selection<C>,
         ) {
             let closure = Closure::wrap(Box::new(move |_: Event| {
                 selected.set(value);
+                settings.persist(setting);
+                setting.notify();
                 let settings = Rc::clone(&settings);
                 wasm_bindgen_futures::spawn_local(async move { animate_back(settings).await });
             }) as Box<dyn FnMut(_)>);
             node.add_event_listener_with_callback("click", closure.as_ref().unchecked_ref())
                 .unwrap();
             closure.forget();
-        };
+        }
 
         fn listen_click_on_back_button(
             settings: Rc<Settings>,
@@ -172,7 +266,7 @@ impl<C: Choice + 'static> Setting for Selection<C> {
             button.add_event_listener_with_callback("click", closure.as_ref().unchecked_ref())
                 .unwrap();
             closure.forget();
-        };
+        }
 
         async fn animate_back(settings: Rc<Settings>) {
             let settings_menu = web_sys::window().unwrap().document().unwrap().query_selector("#among_us_settings_menu").unwrap().unwrap();
@@ -200,6 +294,7 @@ impl<C: Choice + 'static> Setting for Selection<C> {
             selected: Rc<Cell<C>>,
             label: String,
             settings: Rc<Settings>,
+            setting: &'static Selection<C>,
         ) {
             let settings_menu = web_sys::window().unwrap().document().unwrap().query_selector("#among_us_settings_menu").unwrap().unwrap();
 
@@ -229,6 +324,7 @@ impl<C: Choice + 'static> Setting for Selection<C> {
                     item,
                     values[i as usize],
                     Rc::clone(&settings),
+                    setting,
                 );
                 i += 1;
             }
@@ -252,9 +348,10 @@ impl<C: Choice + 'static> Setting for Selection<C> {
             selected: Rc<Cell<C>>,
             label: String,
             settings: Rc<Settings>,
+            setting: &'static Selection<C>,
         ) {
             wasm_bindgen_futures::spawn_local(async move {
-                animate_forward(selected, label, settings).await
+                animate_forward(selected, label, settings, setting).await
             });
         }
 
@@ -263,7 +360,7 @@ impl<C: Choice + 'static> Setting for Selection<C> {
             let label = label.clone();
             let settings = Rc::clone(&settings);
 
-            spawn_animation_task(selected, label, settings);
+            spawn_animation_task(selected, label, settings, self);
         }) as Box<dyn FnMut(_)>);
         self_element
             .add_event_listener_with_callback("click", closure.as_ref().unchecked_ref())
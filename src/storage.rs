@@ -0,0 +1,95 @@
+//! Persists setting values in the browser's local storage.
+//!
+//! Values are stored as text, one `id value` line per setting, after a `schema N` header.
+//! When settings are renamed or their values change meaning, append a migration to [`MIGRATIONS`].
+//! Added settings need nothing: missing values keep their default.
+
+use std::collections::BTreeMap;
+
+const STORAGE_KEY: &str = "among_us_addon_settings";
+
+/// Upgrades stored values from one schema version to the next.
+pub type Migration = fn(&mut BTreeMap<String, String>);
+
+/// `MIGRATIONS[i]` upgrades values from schema `i + 1` to schema `i + 2`.
+pub const MIGRATIONS: &[Migration] = &[];
+
+pub const SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32 + 1;
+
+/// Moves the value of a renamed setting to its new id. Meant to be used in migrations.
+pub fn rename(values: &mut BTreeMap<String, String>, old_id: &str, new_id: &str) {
+    if let Some(value) = values.remove(old_id) {
+        values.insert(new_id.to_string(), value);
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct StoredSettings {
+    values: BTreeMap<String, String>,
+}
+
+impl StoredSettings {
+    /// Parses stored settings, migrating them from older schemas.
+    /// Values from an unknown (newer) schema are dropped.
+    pub fn from_text(text: &str, migrations: &[Migration]) -> StoredSettings {
+        let mut lines = text.lines();
+        let version: u32 = match lines
+            .next()
+            .and_then(|line| line.strip_prefix("schema "))
+            .and_then(|version| version.parse().ok())
+        {
+            Some(version) if version >= 1 && version as usize <= migrations.len() + 1 => version,
+            _ => return StoredSettings::default(),
+        };
+
+        let mut values = BTreeMap::new();
+        for line in lines {
+            if let Some((id, value)) = line.split_once(' ') {
+                values.insert(id.to_string(), value.to_string());
+            }
+        }
+        for migration in &migrations[version as usize - 1..] {
+            migration(&mut values);
+        }
+
+        StoredSettings { values }
+    }
+
+    pub fn to_text(&self, schema_version: u32) -> String {
+        let mut text = format!("schema {}\n", schema_version);
+        for (id, value) in &self.values {
+            text.push_str(&format!("{} {}\n", id, value));
+        }
+        text
+    }
+
+    pub fn get(&self, id: &str) -> Option<&str> {
+        self.values.get(id).map(|value| value.as_str())
+    }
+
+    pub fn set(&mut self, id: &str, value: String) {
+        self.values.insert(id.to_string(), value);
+    }
+
+    /// Reads the settings saved in local storage. Returns empty settings if there are none.
+    pub fn load() -> StoredSettings {
+        let text = web_sys::window()
+            .and_then(|window| window.local_storage().ok().flatten())
+            .and_then(|storage| storage.get_item(STORAGE_KEY).ok().flatten());
+        match text {
+            Some(text) => StoredSettings::from_text(&text, MIGRATIONS),
+            None => StoredSettings::default(),
+        }
+    }
+
+    pub fn save(&self) {
+        let storage = web_sys::window().and_then(|window| window.local_storage().ok().flatten());
+        let saved = match storage {
+            Some(storage) => storage.set_item(STORAGE_KEY, &self.to_text(SCHEMA_VERSION)).is_ok(),
+            None => false,
+        };
+        if !saved {
+            elog!("Failed to save settings");
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use js_sys::Promise;
-use std::time::Duration;
+use std::{cell::Cell, future::Future, pin::Pin, task::Poll, time::Duration};
 use wasm_bindgen_futures::JsFuture;
 use web_sys::window;
 
@@ -15,6 +15,44 @@ pub async fn sleep(duration: Duration) {
     })).await.unwrap();
 }
 
+/// Runs all the futures concurrently and waits until they are all complete.
+pub async fn join_all<F: Future<Output = ()>>(futures: Vec<F>) {
+    let mut futures: Vec<Pin<Box<F>>> = futures.into_iter().map(Box::pin).collect();
+    std::future::poll_fn(move |cx| {
+        futures.retain_mut(|future| future.as_mut().poll(cx).is_pending());
+        match futures.is_empty() {
+            true => Poll::Ready(()),
+            false => Poll::Pending,
+        }
+    })
+    .await
+}
+
+/// Spaces out operations by at least `min_interval`, even when they are started concurrently.
+pub struct RateLimiter {
+    min_interval: Duration,
+    next_slot: Cell<f64>,
+}
+
+impl RateLimiter {
+    pub fn new(min_interval: Duration) -> RateLimiter {
+        RateLimiter {
+            min_interval,
+            next_slot: Cell::new(0.0),
+        }
+    }
+
+    /// Waits for the next free slot.
+    pub async fn wait(&self) {
+        let now = js_sys::Date::now();
+        let slot = f64::max(now, self.next_slot.get());
+        self.next_slot.set(slot + self.min_interval.as_millis() as f64);
+        if slot > now {
+            sleep(Duration::from_millis((slot - now) as u64)).await;
+        }
+    }
+}
+
 macro_rules! log {
     ( $( $t:tt )* ) => {
         web_sys::console::log_1(&format!( $( $t )* ).into());
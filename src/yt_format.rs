@@ -1,8 +1,19 @@
+use crate::{error::AnalysisError, fetch::Fetcher};
 use wasm_bindgen::{JsValue, JsCast};
 use js_sys::Reflect::{get, apply};
 
+/// Extracts the video id from a watch page url.
+pub fn get_video_id(url: &str) -> Option<String> {
+    let start = url.find("watch?v=")? + 8;
+    let id = url[start..].split('&').next()?;
+    match id.is_empty() {
+        true => None,
+        false => Some(id.to_string()),
+    }
+}
+
 pub fn get_game_name(object: &JsValue) -> Option<String> {
-    let mut game_name = get(&object, &"contents".into()).ok()?;
+    let mut game_name = get(object, &"contents".into()).ok()?;
     game_name = get(&game_name, &"twoColumnWatchNextResults".into()).ok()?;
     game_name = get(&game_name, &"results".into()).ok()?;
     game_name = get(&game_name, &"results".into()).ok()?;
@@ -29,10 +40,43 @@ pub fn get_storyboard(mut object: JsValue) -> Option<String> {
     object.as_string()
 }
 
+pub fn get_video_duration(object: &JsValue) -> Option<f64> {
+    let mut duration = get(object, &"videoDetails".into()).ok()?;
+    duration = get(&duration, &"lengthSeconds".into()).ok()?;
+    duration.as_string()?.parse().ok()
+}
+
 pub fn parse_json(text: String) -> Option<JsValue> {
     let json = get(&web_sys::window()?.into(), &"JSON".into()).ok()?;
     let json_parse = get(&json, &"parse".into()).ok()?;
     let params = js_sys::Array::new();
     params.push(&text.into());
     apply(&json_parse.dyn_into().ok()?, &JsValue::NULL, &params).ok()
-}
\ No newline at end of file
+}
+
+/// Downloads the watch page data of a video as json text, the way YouTube does when navigating between videos.
+pub async fn fetch_watch_data(fetcher: &dyn Fetcher, id: &str, identity_token: &str) -> Result<String, AnalysisError> {
+    let headers = [
+        ("X-SPF-Previous", "https://www.youtube.com/"),
+        ("X-SPF-Referer", "https://www.youtube.com/"),
+        ("X-YouTube-Client-Name", "1"),
+        ("X-YouTube-Client-Version", "2.20210110.08.00"),
+        ("x-youtube-csoc", "1"),
+        (
+            "X-YouTube-Device",
+            "cbr=Firefox&cbrver=84.0&ceng=Gecko&cengver=84.0&cos=X11&cplatform=DESKTOP",
+        ),
+        ("X-Youtube-Identity-Token", identity_token),
+        ("X-YouTube-Time-Zone", "Europe/Paris"),
+        ("X-YouTube-Utc-Offset", "60"),
+    ];
+    let response = fetcher
+        .fetch(&format!("https://www.youtube.com/watch?v={}&pbj=1", id), &headers)
+        .await?;
+
+    if response.status != 200 {
+        return Err(AnalysisError::UnexpectedStatus(response.status));
+    }
+
+    String::from_utf8(response.body).map_err(|_| AnalysisError::PageParsing("the pbj response is not valid utf-8"))
+}
@@ -1,3 +1,34 @@
+use crate::{
+    analysis::Timing,
+    classifier::{split_sheet, Image},
+    error::AnalysisError,
+    fetch::Fetcher,
+    util::{join_all, sleep, RateLimiter},
+};
+use std::{
+    cell::{Cell, RefCell},
+    time::Duration,
+};
+
+const MAX_ATTEMPTS: u32 = 4;
+const FIRST_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+pub struct DownloadOptions {
+    /// Maximum number of sheets being downloaded at the same time
+    pub concurrency: usize,
+    /// Minimum delay between the start of two downloads
+    pub min_interval: Duration,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> DownloadOptions {
+        DownloadOptions {
+            concurrency: 4,
+            min_interval: Duration::from_millis(100),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Endpoint {
     start: String,
@@ -8,55 +39,178 @@ pub struct Endpoint {
     height: u8,
     image_width: u8,
     image_height: u8,
+    frame_count: usize,
+    /// Milliseconds between two frames, 0 when YouTube lets the player spread frames over the video
+    interval: u32,
 }
 
 impl Endpoint {
-    pub async fn get_image(&self, n: usize) -> Result<Vec<Image>, &'static str> {
-        use js_sys::Promise;
-        use wasm_bindgen::JsCast;
-        use wasm_bindgen_futures::JsFuture;
-        use web_sys::*;
+    /// Number of tiles per row in a sprite sheet
+    pub fn columns(&self) -> u8 {
+        self.width
+    }
 
-        let mut request: RequestInit = RequestInit::new();
-        request.method("GET");
+    /// Number of tiles per column in a sprite sheet
+    pub fn rows(&self) -> u8 {
+        self.height
+    }
 
-        let window = window().unwrap();
-        let response = match JsFuture::from(window.fetch_with_str_and_init(
-            &format!(
-                "{}{}/M{}.jpg?sqp={}&sigh={}",
-                self.start, self.number, n, self.sqp, self.sigh
-            ),
-            &request,
-        ))
-        .await
-        {
-            Ok(response) => Response::from(response),
-            Err(e) => {
-                elog!("error: {:?}", e);
-                return Err("failed request");
-            }
+    pub fn image_width(&self) -> u8 {
+        self.image_width
+    }
+
+    pub fn image_height(&self) -> u8 {
+        self.image_height
+    }
+
+    /// Total number of frames in the storyboard
+    pub fn frame_count(&self) -> usize {
+        self.frame_count
+    }
+
+    /// Number of sprite sheets needed to hold all the frames
+    pub fn sheet_count(&self) -> usize {
+        let per_sheet = self.width as usize * self.height as usize;
+        if per_sheet == 0 {
+            return 0;
+        }
+        self.frame_count.div_ceil(per_sheet)
+    }
+
+    /// Time between two frames, if the spec declares it
+    pub fn frame_interval(&self) -> Option<f64> {
+        match self.interval {
+            0 => None,
+            interval => Some(interval as f64 / 1000.0),
+        }
+    }
+
+    /// Builds the frame to time mapping of this level.
+    /// `video_duration` is required when the spec does not declare the interval.
+    pub fn timing(&self, video_duration: Option<f64>) -> Result<Timing, AnalysisError> {
+        let interval = match (self.frame_interval(), video_duration) {
+            (Some(interval), _) => interval,
+            (None, Some(duration)) if self.frame_count > 0 => duration / self.frame_count as f64,
+            _ => return Err(AnalysisError::Storyboard("unknown frame interval")),
         };
+        let duration = video_duration.unwrap_or(self.frame_count as f64 * interval);
+
+        Ok(Timing { interval, duration })
+    }
 
-        if response.status() != 200 {
-            return Err("Unexpected response status");
+    pub fn sheet_url(&self, n: usize) -> String {
+        format!(
+            "{}{}/M{}.jpg?sqp={}&sigh={}",
+            self.start, self.number, n, self.sqp, self.sigh
+        )
+    }
+
+    /// Downloads the raw (encoded) sprite sheet number `n`.
+    pub async fn fetch_sheet(&self, fetcher: &dyn Fetcher, n: usize) -> Result<Vec<u8>, AnalysisError> {
+        let response = fetcher.fetch(&self.sheet_url(n), &[]).await?;
+        if response.status != 200 {
+            return Err(AnalysisError::UnexpectedStatus(response.status));
         }
+        Ok(response.body)
+    }
 
-        let blob: Blob = JsFuture::from(response.blob().unwrap())
-            .await
-            .unwrap()
-            .dyn_into()
-            .unwrap();
+    /// Downloads and classifies all the frames of the storyboard, in order.
+    /// Sheets can complete out of order, but `on_frames` is called with the new frames in video order,
+    /// along with the number of downloaded sheets and the total.
+    pub async fn get_all_images(
+        &self,
+        fetcher: &dyn Fetcher,
+        options: &DownloadOptions,
+        on_frames: &dyn Fn(&[Image], usize, usize),
+    ) -> Result<Vec<Image>, AnalysisError> {
+        let sheet_count = self.sheet_count();
+        let next_sheet = Cell::new(0);
+        let completed = Cell::new(0);
+        let sheets: RefCell<Vec<Option<Vec<Image>>>> = RefCell::new((0..sheet_count).map(|_| None).collect());
+        let next_sheet_to_emit = Cell::new(0);
+        let error: RefCell<Option<AnalysisError>> = RefCell::new(None);
+        let rate_limiter = RateLimiter::new(options.min_interval);
+
+        let workers = (0..std::cmp::max(options.concurrency, 1))
+            .map(|_| async {
+                loop {
+                    let n = next_sheet.get();
+                    if n >= sheet_count || error.borrow().is_some() {
+                        break;
+                    }
+                    next_sheet.set(n + 1);
+
+                    rate_limiter.wait().await;
+                    match self.get_image_with_retries(fetcher, n).await {
+                        Ok(mut images) => {
+                            let frames_before = n * self.width as usize * self.height as usize;
+                            images.truncate(self.frame_count.saturating_sub(frames_before));
+                            sheets.borrow_mut()[n] = Some(images);
+                            completed.set(completed.get() + 1);
 
+                            let sheets = sheets.borrow();
+                            while let Some(Some(images)) = sheets.get(next_sheet_to_emit.get()) {
+                                next_sheet_to_emit.set(next_sheet_to_emit.get() + 1);
+                                on_frames(images, completed.get(), sheet_count);
+                            }
+                        }
+                        Err(e) => {
+                            error.borrow_mut().get_or_insert(e);
+                        }
+                    }
+                }
+            })
+            .collect();
+        join_all(workers).await;
+
+        if let Some(e) = error.into_inner() {
+            return Err(e);
+        }
+
+        Ok(sheets.into_inner().into_iter().flatten().flatten().collect())
+    }
+
+    /// Same as [`Endpoint::get_image`] but retries transient failures with an exponential backoff.
+    pub async fn get_image_with_retries(&self, fetcher: &dyn Fetcher, n: usize) -> Result<Vec<Image>, AnalysisError> {
+        let mut delay = FIRST_RETRY_DELAY;
+        let mut attempt = 1;
+        loop {
+            match self.get_image(fetcher, n).await {
+                Err(e) if e.is_transient() && attempt < MAX_ATTEMPTS => {
+                    elog!("sheet {} failed ({}), retrying in {:?}", n, e, delay);
+                    sleep(delay).await;
+                    delay *= 2;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    pub async fn get_image(&self, fetcher: &dyn Fetcher, n: usize) -> Result<Vec<Image>, AnalysisError> {
+        use js_sys::Promise;
+        use wasm_bindgen::JsCast;
+        use wasm_bindgen_futures::JsFuture;
+        use web_sys::*;
+
+        let data = self.fetch_sheet(fetcher, n).await?;
+        let blob = Blob::new_with_u8_array_sequence(&js_sys::Array::of1(&js_sys::Uint8Array::from(&data[..])))
+            .map_err(|_| AnalysisError::ImageDecoding("cannot create a blob"))?;
+
+        let window = window().unwrap();
         let url = Url::create_object_url_with_blob(&blob).unwrap();
         let document = window.document().unwrap();
         let img: HtmlImageElement = document.create_element("img").unwrap().dyn_into().unwrap();
         img.set_src(&url);
-        JsFuture::from(Promise::new(&mut |yes, no| {
+        let loaded = JsFuture::from(Promise::new(&mut |yes, no| {
             img.add_event_listener_with_callback("load", &yes).unwrap();
             img.add_event_listener_with_callback("error", &no).unwrap();
         }))
-        .await
-        .unwrap();
+        .await;
+        if loaded.is_err() {
+            Url::revoke_object_url(&url).unwrap();
+            return Err(AnalysisError::ImageDecoding("the browser cannot load the image"));
+        }
 
         let canvas: HtmlCanvasElement = document
             .create_element("canvas")
@@ -76,42 +230,33 @@ impl Endpoint {
             .draw_image_with_html_image_element(&img, 0.0, 0.0)
             .unwrap();
 
-        let mut images = Vec::new();
-
-        for y in 0..self.height {
-            for x in 0..self.width {
-                let image_data: ImageData = context
-                    .get_image_data(
-                        x as f64 * self.image_width as f64,
-                        y as f64 * self.image_height as f64,
-                        self.image_width as f64,
-                        self.image_height as f64,
-                    )
-                    .unwrap();
-                let data: Vec<u8> = image_data.data().to_vec();
-                images.push(Image::new(data));
-            }
-        }
+        let sheet = context.get_image_data(
+            0.0,
+            0.0,
+            self.width as f64 * self.image_width as f64,
+            self.height as f64 * self.image_height as f64,
+        );
 
         // Clear created data
         img.remove();
         canvas.remove();
         Url::revoke_object_url(&url).unwrap();
 
-        Ok(images)
+        let sheet: ImageData = sheet.map_err(|_| AnalysisError::ImageDecoding("cannot read the canvas"))?;
+        Ok(split_sheet(&sheet.data(), self.width, self.height, self.image_width, self.image_height))
     }
 }
 
-pub fn parse_value(data: String) -> Result<Vec<Endpoint>, &'static str> {
+pub fn parse_value(data: String) -> Result<Vec<Endpoint>, AnalysisError> {
     // https://i.ytimg.com/sb/j370EOXd2RY/storyboard3_L$L/$N.jpg?sqp=-oaymwENSDfyq4qpAwVwAcABAaLzl_8DBgjth6TwBQ==|48#27#100#10#10#0#default#rs$AOn4CLBKGLzTjWWnKLMISMXxpHdX4BiGsQ|80#45#108#10#10#2000#M$M#rs$AOn4CLBA7OWuDEaK8Hah8Tv8jIcbpObXEg|160#90#108#5#5#2000#M$M#rs$AOn4CLC20ooDO3rFTwH0xW6NJwZnsJ8qhQ
     let mut parts: Vec<&str> = data.split('|').collect();
     if parts.is_empty() {
-        return Err("empty value");
+        return Err(AnalysisError::Storyboard("empty value"));
     }
     let start = parts.remove(0);
     let start_parts: Vec<&str> = start.split("$L/$N.jpg?sqp=").collect();
     if start_parts.len() != 2 {
-        return Err("Invalid start value (2 parts expected)");
+        return Err(AnalysisError::Storyboard("Invalid start value (2 parts expected)"));
     }
     let start = start_parts[0];
     let sqp = start_parts[1];
@@ -124,163 +269,38 @@ pub fn parse_value(data: String) -> Result<Vec<Endpoint>, &'static str> {
             sqp: sqp.to_string(),
             number: idx as u8,
             image_width: parts
-                .get(0)
-                .ok_or("Missing image width")?
+                .first()
+                .ok_or(AnalysisError::Storyboard("Missing image width"))?
                 .parse()
-                .map_err(|_| "image width is not a number")?,
+                .map_err(|_| AnalysisError::Storyboard("image width is not a number"))?,
             image_height: parts
                 .get(1)
-                .ok_or("Missing image height")?
+                .ok_or(AnalysisError::Storyboard("Missing image height"))?
                 .parse()
-                .map_err(|_| "image height is not a number")?,
+                .map_err(|_| AnalysisError::Storyboard("image height is not a number"))?,
             width: parts
                 .get(3)
-                .ok_or("Missing width")?
+                .ok_or(AnalysisError::Storyboard("Missing width"))?
                 .parse()
-                .map_err(|_| "width is not a number")?,
+                .map_err(|_| AnalysisError::Storyboard("width is not a number"))?,
             height: parts
                 .get(4)
-                .ok_or("Missing height")?
+                .ok_or(AnalysisError::Storyboard("Missing height"))?
+                .parse()
+                .map_err(|_| AnalysisError::Storyboard("height is not a number"))?,
+            frame_count: parts
+                .get(2)
+                .ok_or(AnalysisError::Storyboard("Missing frame count"))?
+                .parse()
+                .map_err(|_| AnalysisError::Storyboard("frame count is not a number"))?,
+            interval: parts
+                .get(5)
+                .ok_or(AnalysisError::Storyboard("Missing interval"))?
                 .parse()
-                .map_err(|_| "height is not a number")?,
-            sigh: parts.get(7).ok_or("Missing sigh argument")?.to_string(),
+                .map_err(|_| AnalysisError::Storyboard("interval is not a number"))?,
+            sigh: parts.get(7).ok_or(AnalysisError::Storyboard("Missing sigh argument"))?.to_string(),
         });
     }
 
     Ok(endpoints)
 }
-
-pub struct Image {
-    data: Vec<u8>,
-    pub council: bool,
-    pub bright_map: bool,
-    pub impostor_objective: bool,
-    pub open_map: bool,
-    pub game_settings: bool,
-    pub victory_screen: bool,
-    pub defeat_screen: bool,
-    pub alert: bool,
-    pub progress_bar: bool,
-    #[cfg(feature="debugging")]
-    base64: String,
-}
-
-impl Image {
-    pub fn new(data: Vec<u8>) -> Self {
-        let mut image = Image {
-            data,
-            council: false,
-            bright_map: false,
-            impostor_objective: false,
-            open_map: false,
-            game_settings: false,
-            victory_screen: false,
-            defeat_screen: false,
-            alert: false,
-            progress_bar: false,
-            #[cfg(feature="debugging")]
-            base64: String::new(),
-        };
-
-        image.council = image.does_pixels_mean_match(28..74, 16..17, 0xadbfd4, 20);
-        image.bright_map = image.does_pixels_mean_match(152..156, 14..15, 0xc8cbcc, 20) && image.does_pixels_mean_match(153..155, 17..19, 0x54595a, 20);
-        image.impostor_objective = !image.council && image.does_pixels_mean_match(1..39, 12..13, 0x51252b, 20);
-        image.open_map = image.bright_map && (image.does_pixels_mean_match(24..29, 9..14, 0xbdc0c4, 20) || image.does_pixels_mean_match(10..15, 9..15, 0xb9bfbe, 20));
-        image.alert = {
-            let (r,g,b) = image.get_pixels_mean(0..160, 0..30);
-            let diff = r as i32 - (g as i32 + b as i32);
-            if r >= 105 && diff > -40 {
-                let (r,g,b) = image.get_pixels_mean(0..160, 30..60);
-                let diff = r as i32 - (g as i32 + b as i32);
-                if r >= 105 && diff > -40 {
-                    let (r,g,b) = image.get_pixels_mean(0..160, 60..90);
-                    let diff = r as i32 - (g as i32 + b as i32);
-                    r >= 105 && diff > -40
-                } else {
-                    false
-                }
-            } else {
-                false
-            }
-        };
-        image.progress_bar = !image.council && (image.does_pixels_mean_match(2..12, 3..6, 0x72a072, 20) || (image.does_pixels_mean_match(64..71, 3..6, 0x353d38, 20) && image.does_pixels_mean_match(64..71, 2..3, 0x989ca5, 50)));
-        image.game_settings = !image.is_game() && image.does_pixels_mean_match(1..17, 3..68, 0x484949, 15);
-        image.victory_screen = !image.is_game() && image.does_pixels_mean_match(49..111, 12..21, 0x163150, 16) && image.does_pixels_mean_match(40..120, 25..41, 0x000000, 10);
-        image.defeat_screen = !image.is_game() && image.does_pixels_mean_match(53..105, 9..23, 0x470c10, 10) && image.does_pixels_mean_match(40..120, 25..41, 0x090807, 10);
-
-        #[cfg(feature="debugging")]
-        {
-            use image::{ImageBuffer, RgbImage};
-            let mut img: RgbImage = ImageBuffer::new(160, 90);
-            for x in 0..160 {
-                for y in 0..90 {
-                    let (r, g, b) = image.get_pixel(x, y);
-                    img.put_pixel(x as u32, y as u32, image::Rgb([r, g, b]));
-                }
-            }
-            let mut output = Vec::new();
-            let encoder = image::codecs::png::PngEncoder::new(&mut output);
-            encoder
-                .encode(&img, 160, 90, image::ColorType::Rgb8)
-                .unwrap();
-            image.base64 = base64::encode(output);
-        }
-
-        image
-    }
-
-    pub fn get_pixel(&self, x: u8, y: u8) -> (u8, u8, u8) {
-        (
-            self.data[y as usize * 4 * 160 + x as usize * 4],
-            self.data[y as usize * 4 * 160 + x as usize * 4 + 1],
-            self.data[y as usize * 4 * 160 + x as usize * 4 + 2],
-        )
-    }
-
-    pub fn get_pixels_mean(&self, x_range: std::ops::Range<u8>, y_range: std::ops::Range<u8>) -> (u8, u8, u8) {
-        let mut r: u64 = 0;
-        let mut g: u64 = 0;
-        let mut b: u64 = 0;
-
-        for x in x_range.clone() {
-            for y in y_range.clone() {
-                let (r2, g2, b2) = self.get_pixel(x, y);
-                r += r2 as u64;
-                g += g2 as u64;
-                b += b2 as u64;
-            }
-        }
-
-        let number = (x_range.end - x_range.start) as u64 * (y_range.end - y_range.start) as u64;
-        r /= number;
-        g /= number;
-        b /= number;
-
-        (r as u8, g as u8, b as u8)
-    }
-
-    pub fn does_pixels_mean_match(&self, x_range: std::ops::Range<u8>, y_range: std::ops::Range<u8>, expected: u32, tolerance: u8) -> bool {
-        let [_, expected_r, expected_g, expected_b] = expected.to_be_bytes();
-        let got = self.get_pixels_mean(x_range, y_range);
-        std::cmp::max(got.0, expected_r) - std::cmp::min(got.0, expected_r) <= tolerance
-            && std::cmp::max(got.1, expected_g) - std::cmp::min(got.1, expected_g) <= tolerance
-            && std::cmp::max(got.2, expected_b) - std::cmp::min(got.2, expected_b) <= tolerance
-    }
-
-    pub fn does_pixel_match(&self, x: u8, y: u8, expected: u32, tolerance: u8) -> bool {
-        let [_, expected_r, expected_g, expected_b] = expected.to_be_bytes();
-        let got = self.get_pixel(x, y);
-        std::cmp::max(got.0, expected_r) - std::cmp::min(got.0, expected_r) <= tolerance
-            && std::cmp::max(got.1, expected_g) - std::cmp::min(got.1, expected_g) <= tolerance
-            && std::cmp::max(got.2, expected_b) - std::cmp::min(got.2, expected_b) <= tolerance
-    }
-
-    #[cfg(feature="debugging")]
-    pub fn base64(&self) -> &str {
-        &self.base64
-    }
-
-    pub fn is_game(&self) -> bool {
-        self.bright_map || self.council || self.alert || self.progress_bar
-    }
-}
@@ -0,0 +1,91 @@
+mod common;
+
+use addon::{
+    analysis::{Death, Ejection, Game, Meeting, MeetingTrigger, Outcome, Sabotage, TaskProgress, Timing, ANALYSER_VERSION},
+    cache::CachedAnalysis,
+};
+
+fn analysis() -> CachedAnalysis {
+    CachedAnalysis {
+        frame_count: 1342,
+        timing: Timing {
+            interval: 2.0,
+            duration: 2683.4,
+        },
+        games: vec![
+            Game {
+                impostor: true,
+                outcome: Outcome::Win,
+                meetings: vec![
+                    Meeting {
+                        frames: 50..60,
+                        start: 100.0,
+                        end: 120.0,
+                        trigger: MeetingTrigger::BodyReport,
+                        ejection: Ejection::Player,
+                    },
+                    Meeting {
+                        frames: 200..207,
+                        start: 400.0,
+                        end: 414.0,
+                        trigger: MeetingTrigger::EmergencyButton,
+                        ejection: Ejection::NoOne,
+                    },
+                ],
+                sabotages: vec![Sabotage {
+                    frames: 120..135,
+                    start: 240.0,
+                    end: 270.0,
+                }],
+                death: Some(Death {
+                    frame: 201,
+                    time: 402.0,
+                }),
+                ..common::game(12..240, 24.0, 480.0)
+            },
+            Game {
+                task_progress: vec![
+                    TaskProgress {
+                        frame: 310,
+                        time: 620.0,
+                        completion: 0.0,
+                    },
+                    TaskProgress {
+                        frame: 402,
+                        time: 804.0,
+                        completion: 0.2753623188405797,
+                    },
+                ],
+                ..common::game(300..1342, 600.0, 2683.4)
+            },
+        ],
+    }
+}
+
+#[test]
+fn round_trip() {
+    let text = analysis().to_text();
+    assert_eq!(CachedAnalysis::from_text(&text), Some(analysis()));
+
+    let empty = CachedAnalysis { games: Vec::new(), ..analysis() };
+    assert_eq!(CachedAnalysis::from_text(&empty.to_text()), Some(empty));
+}
+
+#[test]
+fn invalidation() {
+    let text = analysis().to_text();
+    let outdated = text.replacen(
+        &format!("version {}", ANALYSER_VERSION),
+        &format!("version {}", ANALYSER_VERSION + 1),
+        1,
+    );
+    assert_eq!(CachedAnalysis::from_text(&outdated), None);
+
+    assert_eq!(CachedAnalysis::from_text(""), None);
+    assert_eq!(CachedAnalysis::from_text(&text.replace("impostor", "ghost")), None);
+
+    // A meeting must follow its game
+    let header_end = text.find("game ").unwrap();
+    let orphan_meeting = format!("{}meeting 1 2 2 4 unknown unknown\n{}", &text[..header_end], &text[header_end..]);
+    assert_eq!(CachedAnalysis::from_text(&orphan_meeting), None);
+}
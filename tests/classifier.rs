@@ -0,0 +1,99 @@
+use addon::classifier::{split_sheet, Image, FRAME_HEIGHT, FRAME_WIDTH};
+
+fn load_fixture(name: &str) -> Image {
+    let path = format!("{}/tests/fixtures/frames/{}.png", env!("CARGO_MANIFEST_DIR"), name);
+    let frame = image::open(&path).unwrap_or_else(|e| panic!("cannot open {}: {}", path, e)).to_rgba8();
+    assert_eq!(frame.dimensions(), (FRAME_WIDTH as u32, FRAME_HEIGHT as u32));
+    Image::new(frame.into_raw())
+}
+
+fn flags(image: &Image) -> Vec<&'static str> {
+    let mut flags = Vec::new();
+    if image.is_game() { flags.push("game") }
+    if image.council { flags.push("council") }
+    if image.bright_map { flags.push("bright_map") }
+    if image.impostor_objective { flags.push("impostor_objective") }
+    if image.open_map { flags.push("open_map") }
+    if image.game_settings { flags.push("game_settings") }
+    if image.victory_screen { flags.push("victory_screen") }
+    if image.defeat_screen { flags.push("defeat_screen") }
+    if image.alert { flags.push("alert") }
+    if image.progress_bar { flags.push("progress_bar") }
+    if image.impostor_reveal { flags.push("impostor_reveal") }
+    if image.crewmate_reveal { flags.push("crewmate_reveal") }
+    if image.ejection { flags.push("ejection") }
+    if image.player_ejected { flags.push("player_ejected") }
+    if image.emergency_meeting { flags.push("emergency_meeting") }
+    if image.body_reported { flags.push("body_reported") }
+    if image.sabotage_alarm { flags.push("sabotage_alarm") }
+    if image.ghost { flags.push("ghost") }
+    flags
+}
+
+#[test]
+fn fixture_frames() {
+    let expectations: &[(&str, &[&str])] = &[
+        ("black", &[]),
+        ("crewmate", &["game", "bright_map", "progress_bar"]),
+        ("impostor", &["game", "bright_map", "impostor_objective"]),
+        ("open_map", &["game", "bright_map", "open_map", "progress_bar"]),
+        ("council", &["game", "council"]),
+        ("alert", &["game", "alert"]),
+        ("game_settings", &["game_settings"]),
+        ("victory", &["victory_screen"]),
+        ("defeat", &["defeat_screen"]),
+        ("impostor_reveal", &["impostor_reveal"]),
+        ("crewmate_reveal", &["crewmate_reveal"]),
+        ("ejection_player", &["ejection", "player_ejected"]),
+        ("ejection_no_one", &["ejection"]),
+        ("emergency_meeting", &["game", "alert", "emergency_meeting"]),
+        ("body_reported", &["game", "alert", "body_reported"]),
+        ("sabotage", &["game", "bright_map", "progress_bar", "sabotage_alarm"]),
+        ("pale_floor", &["game", "bright_map", "progress_bar"]),
+        ("ghost_text", &["game", "bright_map", "progress_bar", "ghost"]),
+        ("impostor_red_text", &["game", "bright_map", "impostor_objective", "progress_bar"]),
+    ];
+
+    for (name, expected) in expectations {
+        assert_eq!(&flags(&load_fixture(name)), expected, "fixture {}", name);
+    }
+}
+
+#[test]
+fn task_bar_fill() {
+    let expectations: &[(&str, Option<f64>)] = &[
+        ("tasks_empty", Some(0.0)),
+        ("tasks_half", Some(34.0 / 69.0)),
+        ("tasks_full", Some(1.0)),
+        ("crewmate", Some(58.0 / 69.0)),
+        ("council", None),
+        ("impostor", None),
+    ];
+
+    for (name, expected) in expectations {
+        let image = load_fixture(name);
+        assert_eq!(flags(&image).contains(&"progress_bar"), expected.is_some(), "fixture {}", name);
+        assert_eq!(image.task_completion, *expected, "fixture {}", name);
+    }
+}
+
+#[test]
+fn sheet_split_order() {
+    let (columns, rows) = (3, 2);
+    let tile_len = FRAME_WIDTH as usize * FRAME_HEIGHT as usize * 4;
+    let sheet_width = columns * FRAME_WIDTH as usize;
+    let mut sheet = vec![0; tile_len * columns * rows];
+    for (idx, pixel) in sheet.chunks_mut(4).enumerate() {
+        let (x, y) = (idx % sheet_width, idx / sheet_width);
+        pixel[0] = (x / FRAME_WIDTH as usize) as u8;
+        pixel[1] = (y / FRAME_HEIGHT as usize) as u8;
+    }
+
+    let images = split_sheet(&sheet, columns as u8, rows as u8, FRAME_WIDTH, FRAME_HEIGHT);
+    assert_eq!(images.len(), 6);
+    for (idx, image) in images.iter().enumerate() {
+        let expected = ((idx % columns) as u8, (idx / columns) as u8, 0);
+        assert_eq!(image.get_pixel(0, 0), expected);
+        assert_eq!(image.get_pixel(FRAME_WIDTH - 1, FRAME_HEIGHT - 1), expected);
+    }
+}
@@ -0,0 +1,28 @@
+mod common;
+
+use addon::analysis::{timestamp_summary, Game, Outcome, WinReason};
+
+fn game(start: f64, end: f64, impostor: bool, outcome: Outcome, win_reason: WinReason) -> Game {
+    Game {
+        impostor,
+        outcome,
+        win_reason,
+        ..common::game(0..1, start, end)
+    }
+}
+
+#[test]
+fn summary() {
+    let games = vec![
+        game(12.0, 300.0, false, Outcome::Win, WinReason::Tasks),
+        game(330.5, 754.0, true, Outcome::Loss, WinReason::Votes),
+        game(754.9, 3700.0, true, Outcome::Unknown, WinReason::Unknown),
+    ];
+    assert_eq!(
+        timestamp_summary(&games),
+        "0:00:12 Game 1 \u{2014} Crewmate \u{2014} Win (tasks)\n\
+         0:05:30 Game 2 \u{2014} Impostor \u{2014} Loss (votes)\n\
+         0:12:34 Game 3 \u{2014} Impostor\n"
+    );
+    assert_eq!(timestamp_summary(&[]), "");
+}
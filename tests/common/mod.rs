@@ -0,0 +1,20 @@
+//! Helpers shared by the integration tests.
+
+use addon::analysis::{Game, Outcome, WinReason};
+use std::ops::Range;
+
+/// A game where nothing but its bounds was detected, to be completed with the struct update syntax.
+pub fn game(frames: Range<usize>, start: f64, end: f64) -> Game {
+    Game {
+        frames,
+        start,
+        end,
+        impostor: false,
+        outcome: Outcome::Unknown,
+        win_reason: WinReason::Unknown,
+        meetings: Vec::new(),
+        sabotages: Vec::new(),
+        death: None,
+        task_progress: Vec::new(),
+    }
+}
@@ -0,0 +1,72 @@
+use addon::{
+    error::AnalysisError,
+    fetch::MemoryFetcher,
+    yt_format::{fetch_watch_data, get_video_id},
+    ytimg::parse_value,
+};
+use std::{
+    future::Future,
+    task::{Context, Poll, Waker},
+};
+
+const SPEC: &str = "https://i.ytimg.com/sb/j370EOXd2RY/storyboard3_L$L/$N.jpg?sqp=-oaymw|48#27#100#10#10#0#default#rs$A|80#45#108#10#10#2000#M$M#rs$B|160#90#108#5#5#2000#M$M#rs$C";
+
+/// `MemoryFetcher` futures are always ready, so there is no need for a real executor.
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = Box::pin(future);
+    match future.as_mut().poll(&mut Context::from_waker(Waker::noop())) {
+        Poll::Ready(output) => output,
+        Poll::Pending => panic!("the future is not ready"),
+    }
+}
+
+#[test]
+fn sheet_requests() {
+    let endpoints = parse_value(SPEC.to_string()).unwrap();
+    let mut fetcher = MemoryFetcher::new();
+    fetcher.add_response(
+        "https://i.ytimg.com/sb/j370EOXd2RY/storyboard3_L2/M0.jpg?sqp=-oaymw&sigh=rs$C",
+        200,
+        &b"sheet"[..],
+    );
+
+    assert_eq!(block_on(endpoints[2].fetch_sheet(&fetcher, 0)), Ok(b"sheet".to_vec()));
+    assert_eq!(block_on(endpoints[2].fetch_sheet(&fetcher, 1)), Err(AnalysisError::UnexpectedStatus(404)));
+    assert_eq!(fetcher.requests().len(), 2);
+}
+
+#[test]
+fn video_id() {
+    assert_eq!(get_video_id("https://www.youtube.com/watch?v=kofC4k2tm68"), Some("kofC4k2tm68".to_string()));
+    assert_eq!(
+        get_video_id("https://www.youtube.com/watch?v=kofC4k2tm68&ab_channel=DomingoReplay"),
+        Some("kofC4k2tm68".to_string())
+    );
+    assert_eq!(get_video_id("https://www.youtube.com/feed/subscriptions"), None);
+}
+
+#[test]
+fn transient_errors() {
+    assert!(AnalysisError::Network("reset".to_string()).is_transient());
+    assert!(AnalysisError::UnexpectedStatus(503).is_transient());
+    assert!(AnalysisError::UnexpectedStatus(429).is_transient());
+    assert!(!AnalysisError::UnexpectedStatus(404).is_transient());
+    assert!(!AnalysisError::ImageDecoding("corrupted").is_transient());
+}
+
+#[test]
+fn watch_data_request() {
+    let mut fetcher = MemoryFetcher::new();
+    fetcher.add_response("https://www.youtube.com/watch?v=kofC4k2tm68&pbj=1", 200, &b"[{}]"[..]);
+
+    assert_eq!(block_on(fetch_watch_data(&fetcher, "kofC4k2tm68", "token")), Ok("[{}]".to_string()));
+    let request = &fetcher.requests()[0];
+    assert!(request
+        .headers
+        .contains(&("X-Youtube-Identity-Token".to_string(), "token".to_string())));
+
+    assert_eq!(
+        block_on(fetch_watch_data(&fetcher, "unknown", "token")),
+        Err(AnalysisError::UnexpectedStatus(404))
+    );
+}
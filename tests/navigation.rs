@@ -0,0 +1,76 @@
+mod common;
+
+use addon::analysis::{death_revealed, next_game, outcome_revealed, previous_game, role_revealed, skip_to, Death, Game};
+
+fn games() -> Vec<Game> {
+    [(10.0, false), (100.0, true), (200.0, false), (300.0, true)]
+        .iter()
+        .map(|&(start, impostor)| Game {
+            impostor,
+            ..common::game(0..1, start, start + 90.0)
+        })
+        .collect()
+}
+
+fn index(game: Option<(usize, &Game)>) -> Option<usize> {
+    game.map(|(idx, _)| idx)
+}
+
+#[test]
+fn next() {
+    let games = games();
+    assert_eq!(index(next_game(&games, 0.0, false)), Some(0));
+    assert_eq!(index(next_game(&games, 10.0, false)), Some(1));
+    assert_eq!(index(next_game(&games, 150.0, false)), Some(2));
+    assert_eq!(index(next_game(&games, 150.0, true)), Some(3));
+    assert_eq!(index(next_game(&games, 300.0, false)), None);
+}
+
+#[test]
+fn previous() {
+    let games = games();
+    assert_eq!(index(previous_game(&games, 250.0, false)), Some(2));
+    assert_eq!(index(previous_game(&games, 250.0, true)), Some(1));
+    // Right after a game started, going back selects the game before it
+    assert_eq!(index(previous_game(&games, 201.0, false)), Some(1));
+    assert_eq!(index(previous_game(&games, 11.0, false)), None);
+    assert_eq!(index(previous_game(&games, 90.0, true)), None);
+}
+
+#[test]
+fn auto_skip() {
+    let games = games();
+    // Before the first impostor game, and during a crewmate game
+    assert_eq!(skip_to(&games, 0.0, 400.0, 400.0), Some(100.0));
+    assert_eq!(skip_to(&games, 50.0, 400.0, 400.0), Some(100.0));
+    // Between games
+    assert_eq!(skip_to(&games, 195.0, 400.0, 400.0), Some(300.0));
+    // During an impostor game, or right before its start after a seek
+    assert_eq!(skip_to(&games, 150.0, 400.0, 400.0), None);
+    assert_eq!(skip_to(&games, 99.5, 400.0, 400.0), None);
+    // After the last impostor game
+    assert_eq!(skip_to(&games, 395.0, 400.0, 400.0), Some(400.0));
+    assert_eq!(skip_to(&games, 395.0, 398.0, 400.0), None);
+    // The video is still being analysed
+    assert_eq!(skip_to(&games[..2], 250.0, 260.0, 400.0), None);
+    assert_eq!(skip_to(&games[..2], 270.0, 260.0, 400.0), None);
+}
+
+#[test]
+fn spoilers() {
+    let games = games();
+    assert!(!role_revealed(&games[1], 50.0));
+    assert!(!role_revealed(&games[1], 99.0));
+    assert!(role_revealed(&games[1], 100.0));
+    assert!(role_revealed(&games[1], 250.0));
+    assert!(!outcome_revealed(&games[1], 150.0));
+    assert!(outcome_revealed(&games[1], 190.0));
+
+    // Surviving is only known once the game is over
+    assert!(!death_revealed(&games[1], 150.0));
+    assert!(death_revealed(&games[1], 190.0));
+    let mut game = games[2].clone();
+    game.death = Some(Death { frame: 0, time: 250.0 });
+    assert!(!death_revealed(&game, 240.0));
+    assert!(death_revealed(&game, 250.0));
+}
@@ -0,0 +1,233 @@
+//! Golden-file tests for `analysis::find_games`.
+//!
+//! Each file in `tests/fixtures/segmentation` describes one video with two sections:
+//!
+//! ```text
+//! [frames]
+//! 3 -                                  # three frames without any flag
+//! 2 bright_map progress_bar            # two frames with these flags set
+//! 2 council
+//! 1 bright_map progress_bar
+//! [games]
+//! 3..8 crewmate unknown unknown        # role, outcome and win reason
+//! meeting 5..7 unknown unknown         # meetings, sabotages, deaths and task bar changes follow their game
+//! ```
+//!
+//! Flags are the names of the public `Image` fields,
+//! or `tasks=` followed by a percentage to set `Image::task_completion`.
+//! Frames are dark gray without any flag set, except `black` frames which stand for the padding
+//! at the end of the last sprite sheet.
+//! Run with `UPDATE_GOLDEN=1` to rewrite the `[games]` sections after an intended change,
+//! and bump `analysis::ANALYSER_VERSION` so that cached analyses are recomputed.
+
+use addon::{
+    analysis::{find_games, Game, Segmenter, Timing},
+    classifier::{Image, FRAME_HEIGHT, FRAME_WIDTH},
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+fn blank_image(value: u8) -> Image {
+    Image::new(vec![value; FRAME_WIDTH as usize * FRAME_HEIGHT as usize * 4])
+}
+
+fn set_flag(image: &mut Image, flag: &str) -> Result<(), String> {
+    match flag {
+        "council" => image.council = true,
+        "bright_map" => image.bright_map = true,
+        "impostor_objective" => image.impostor_objective = true,
+        "open_map" => image.open_map = true,
+        "game_settings" => image.game_settings = true,
+        "victory_screen" => image.victory_screen = true,
+        "defeat_screen" => image.defeat_screen = true,
+        "alert" => image.alert = true,
+        "progress_bar" => image.progress_bar = true,
+        "impostor_reveal" => image.impostor_reveal = true,
+        "crewmate_reveal" => image.crewmate_reveal = true,
+        "ejection" => image.ejection = true,
+        "player_ejected" => image.player_ejected = true,
+        "emergency_meeting" => image.emergency_meeting = true,
+        "body_reported" => image.body_reported = true,
+        "sabotage_alarm" => image.sabotage_alarm = true,
+        "ghost" => image.ghost = true,
+        "-" | "black" => (),
+        flag if flag.starts_with("tasks=") => {
+            let percent: f64 = flag["tasks=".len()..].parse().map_err(|_| format!("invalid task completion {:?}", flag))?;
+            image.task_completion = Some(percent / 100.0);
+        }
+        flag => return Err(format!("unknown flag {:?}", flag)),
+    }
+    Ok(())
+}
+
+struct Video {
+    frames_section: String,
+    images: Vec<Image>,
+    expected: Vec<String>,
+}
+
+fn parse_video(content: &str) -> Result<Video, String> {
+    let mut frames_section = String::new();
+    let mut images = Vec::new();
+    let mut expected = Vec::new();
+    let mut section = None;
+
+    for (line_idx, raw_line) in content.lines().enumerate() {
+        let line = raw_line.split('#').next().unwrap().trim();
+        if line == "[frames]" || line == "[games]" {
+            section = Some(line);
+        } else if section == Some("[games]") {
+            if !line.is_empty() {
+                expected.push(line.to_string());
+            }
+            continue;
+        } else if section == Some("[frames]") && !line.is_empty() {
+            let mut words = line.split_whitespace();
+            let count: usize = words
+                .next()
+                .unwrap()
+                .parse()
+                .map_err(|_| format!("line {}: expected a frame count", line_idx + 1))?;
+            let flags: Vec<&str> = words.collect();
+            for _ in 0..count {
+                let mut image = blank_image(if flags.contains(&"black") { 0 } else { 1 });
+                for flag in &flags {
+                    set_flag(&mut image, flag).map_err(|e| format!("line {}: {}", line_idx + 1, e))?;
+                }
+                images.push(image);
+            }
+        } else if !line.is_empty() {
+            return Err(format!("line {}: outside of any section", line_idx + 1));
+        }
+
+        if section != Some("[games]") {
+            frames_section.push_str(raw_line);
+            frames_section.push('\n');
+        }
+    }
+
+    Ok(Video {
+        frames_section,
+        images,
+        expected,
+    })
+}
+
+/// One line for the game, then one per meeting, one per sabotage, one for the death and one per task bar change
+fn format_game(game: &Game) -> Vec<String> {
+    let mut lines = vec![format!(
+        "{}..{} {} {} {}",
+        game.frames.start,
+        game.frames.end,
+        if game.impostor { "impostor" } else { "crewmate" },
+        game.outcome.name(),
+        game.win_reason.name()
+    )];
+    for meeting in &game.meetings {
+        lines.push(format!(
+            "meeting {}..{} {} {}",
+            meeting.frames.start,
+            meeting.frames.end,
+            meeting.ejection.name(),
+            meeting.trigger.name()
+        ));
+    }
+    for sabotage in &game.sabotages {
+        lines.push(format!("sabotage {}..{}", sabotage.frames.start, sabotage.frames.end));
+    }
+    if let Some(death) = game.death {
+        lines.push(format!("death {}", death.frame));
+    }
+    for progress in &game.task_progress {
+        lines.push(format!("tasks {} {}%", progress.frame, (progress.completion * 100.0).round()));
+    }
+    lines
+}
+
+fn diff(expected: &[String], got: &[String]) -> String {
+    let mut report = String::new();
+    for line in expected.iter().filter(|line| !got.contains(line)) {
+        report.push_str(&format!("  - {}\n", line));
+    }
+    for line in got.iter().filter(|line| !expected.contains(line)) {
+        report.push_str(&format!("  + {}\n", line));
+    }
+    if report.is_empty() {
+        report.push_str("  (same games in a different order)\n");
+    }
+    report
+}
+
+fn dataset_paths() -> Vec<PathBuf> {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/segmentation");
+    let mut paths: Vec<_> = fs::read_dir(&directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().map(|e| e == "txt").unwrap_or(false))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "no dataset in {}", directory.display());
+    paths
+}
+
+fn timing(video: &Video) -> Timing {
+    Timing {
+        interval: 2.0,
+        duration: video.images.len() as f64 * 2.0,
+    }
+}
+
+#[test]
+fn golden_segmentation() {
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+
+    let mut failures = String::new();
+    for path in dataset_paths() {
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        let video = match parse_video(&fs::read_to_string(&path).unwrap()) {
+            Ok(video) => video,
+            Err(e) => {
+                failures.push_str(&format!("{}: invalid dataset: {}\n", name, e));
+                continue;
+            }
+        };
+
+        let got: Vec<String> = find_games(&video.images, &timing(&video)).iter().flat_map(format_game).collect();
+        if got == video.expected {
+            continue;
+        }
+        if update {
+            let mut content = video.frames_section;
+            content.push_str("[games]\n");
+            for line in &got {
+                content.push_str(line);
+                content.push('\n');
+            }
+            fs::write(&path, content).unwrap();
+            continue;
+        }
+        failures.push_str(&format!("{} ({} frames):\n{}", name, video.images.len(), diff(&video.expected, &got)));
+    }
+
+    assert!(failures.is_empty(), "segmentation changed:\n{}", failures);
+}
+
+#[test]
+fn incremental_segmentation() {
+    for path in dataset_paths() {
+        let video = parse_video(&fs::read_to_string(&path).unwrap()).unwrap();
+        let games = find_games(&video.images, &timing(&video));
+
+        // Finished games show up as soon as their end frame is pushed, and never change afterwards
+        let mut segmenter = Segmenter::new(timing(&video));
+        for image in &video.images {
+            segmenter.push(image);
+            let finished = segmenter.games();
+            assert_eq!(finished, &games[..finished.len()], "{}", path.display());
+            assert!(finished.iter().all(|game| game.frames.end < segmenter.analysed_frames()));
+        }
+        assert_eq!(segmenter.finish(), games, "{}", path.display());
+    }
+}
@@ -0,0 +1,41 @@
+use addon::storage::{rename, Migration, StoredSettings, MIGRATIONS, SCHEMA_VERSION};
+use std::collections::BTreeMap;
+
+#[test]
+fn round_trip() {
+    let mut settings = StoredSettings::default();
+    settings.set("amgus_ext_comments", "false".to_string());
+    settings.set("amgus_ext_theme", "Dark Blue".to_string());
+
+    let text = settings.to_text(SCHEMA_VERSION);
+    assert!(text.starts_with(&format!("schema {}\n", SCHEMA_VERSION)));
+    let loaded = StoredSettings::from_text(&text, MIGRATIONS);
+    assert_eq!(loaded, settings);
+    assert_eq!(loaded.get("amgus_ext_theme"), Some("Dark Blue"));
+    assert_eq!(loaded.get("amgus_ext_unknown"), None);
+}
+
+#[test]
+fn migrations() {
+    fn rename_theme(values: &mut BTreeMap<String, String>) {
+        rename(values, "amgus_ext_theme", "amgus_ext_bar_theme");
+    }
+    fn drop_comments(values: &mut BTreeMap<String, String>) {
+        values.remove("amgus_ext_comments");
+    }
+    let migrations: &[Migration] = &[rename_theme, drop_comments];
+
+    let v1 = "schema 1\namgus_ext_comments true\namgus_ext_theme Light\n";
+    let migrated = StoredSettings::from_text(v1, migrations);
+    assert_eq!(migrated.get("amgus_ext_bar_theme"), Some("Light"));
+    assert_eq!(migrated.get("amgus_ext_theme"), None);
+    assert_eq!(migrated.get("amgus_ext_comments"), None);
+
+    // Only the migrations after the stored schema run
+    let v3 = "schema 3\namgus_ext_comments true\namgus_ext_theme Light\n";
+    assert_eq!(StoredSettings::from_text(v3, migrations).get("amgus_ext_theme"), Some("Light"));
+
+    // Settings saved by a newer version or corrupted are ignored
+    assert_eq!(StoredSettings::from_text("schema 4\na b\n", migrations), StoredSettings::default());
+    assert_eq!(StoredSettings::from_text("garbage", migrations), StoredSettings::default());
+}
@@ -0,0 +1,50 @@
+use addon::{
+    analysis::{format_timestamp, Timing},
+    error::AnalysisError,
+    ytimg::parse_value,
+};
+
+const SPEC: &str = "https://i.ytimg.com/sb/j370EOXd2RY/storyboard3_L$L/$N.jpg?sqp=-oaymw|48#27#100#10#10#0#default#rs$A|80#45#108#10#10#2000#M$M#rs$B|160#90#108#5#5#2000#M$M#rs$C";
+
+#[test]
+fn levels() {
+    let endpoints = parse_value(SPEC.to_string()).unwrap();
+    assert_eq!(endpoints.len(), 3);
+
+    let endpoint = &endpoints[2];
+    assert_eq!((endpoint.image_width(), endpoint.image_height()), (160, 90));
+    assert_eq!((endpoint.columns(), endpoint.rows()), (5, 5));
+    assert_eq!(endpoint.frame_count(), 108);
+    assert_eq!(endpoint.sheet_count(), 5);
+    assert_eq!(endpoints[0].sheet_count(), 1);
+    assert_eq!(endpoint.frame_interval(), Some(2.0));
+
+    assert_eq!(endpoints[0].frame_interval(), None);
+    assert_eq!(
+        parse_value("https://i.ytimg.com/sb/x/storyboard3_L$L/$N.jpg?sqp=a|160#90#?#5#5#2000#M$M#rs$C".to_string()).unwrap_err(),
+        AnalysisError::Storyboard("frame count is not a number")
+    );
+}
+
+#[test]
+fn timing() {
+    let endpoints = parse_value(SPEC.to_string()).unwrap();
+
+    // The declared interval wins, the video duration only clamps the last frames
+    let timing = endpoints[2].timing(Some(211.5)).unwrap();
+    assert_eq!(timing, Timing { interval: 2.0, duration: 211.5 });
+    assert_eq!(timing.frame_time(10), 20.0);
+    assert_eq!(timing.frame_time(107), 211.5);
+    assert_eq!(endpoints[2].timing(None).unwrap().duration, 216.0);
+
+    // Without interval, frames are spread over the whole video
+    assert_eq!(endpoints[0].timing(Some(300.0)).unwrap().interval, 3.0);
+    assert!(endpoints[0].timing(None).is_err());
+}
+
+#[test]
+fn timestamps() {
+    assert_eq!(format_timestamp(0.0), "0:00:00");
+    assert_eq!(format_timestamp(754.9), "0:12:34");
+    assert_eq!(format_timestamp(3600.0 * 2.0 + 61.0), "2:01:01");
+}